@@ -1,11 +1,11 @@
-use crate::{FormRequest, OpenAiClient, process_response, process_text_response};
+use crate::{FormRequest, OpenAiClient, process_response, process_text_response, Result};
+use crate::meta::{ResponseMeta, WithMeta};
 use std::io;
 use std::path::{PathBuf};
 use reqwest::multipart::{Form, Part};
 use serde::{Serialize,Deserialize};
 use crate::file_to_part;
 use async_trait::async_trait;
-use anyhow::Result;
 use futures_util::TryFutureExt;
 use reqwest::Response;
 use strum_macros::Display;
@@ -20,14 +20,14 @@ pub enum ResponseFormat{
     Vtt
 }
 
-impl ToString for ResponseFormat {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ResponseFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ResponseFormat::Json => "json".to_string(),
-            ResponseFormat::Text => "text".to_string(),
-            ResponseFormat::Srt => "srt".to_string(),
-            ResponseFormat::VerboseJson => "verbose_json".to_string(),
-            ResponseFormat::Vtt => "vtt".to_string()
+            ResponseFormat::Json => write!(f,"json"),
+            ResponseFormat::Text => write!(f,"text"),
+            ResponseFormat::Srt => write!(f,"srt"),
+            ResponseFormat::VerboseJson => write!(f,"verbose_json"),
+            ResponseFormat::Vtt => write!(f,"vtt")
         }
     }
 }
@@ -104,10 +104,18 @@ impl FormRequest<AudioResponse> for TranscriptionRequest{
     const ENDPOINT: &'static str = "/audio/transcriptions";
 
     async fn run(&self, client:&OpenAiClient)-> Result<AudioResponse>{
-        let final_url =  client.url.to_owned()+Self::ENDPOINT;
-        let res = self.get_response(&client.client,final_url,&client.key).await?;
+        let final_url = client.url_for(Self::ENDPOINT, "", || Some(self.model.clone()));
+        let res = self.get_response(client,final_url).await?;
         process_audio_response(&self.response_format,res).await
     }
+
+    async fn run_with_meta(&self, client:&OpenAiClient)-> Result<WithMeta<AudioResponse>>{
+        let final_url = client.url_for(Self::ENDPOINT, "", || Some(self.model.clone()));
+        let res = self.get_response(client,final_url).await?;
+        let meta = ResponseMeta::from_headers(res.status(), res.headers());
+        let body = process_audio_response(&self.response_format,res).await?;
+        Ok(WithMeta { body, meta })
+    }
 }
 
 
@@ -226,10 +234,18 @@ impl FormRequest<AudioResponse> for TranslationRequest{
     const ENDPOINT: &'static str = "/audio/translations";
 
     async fn run(&self, client:&OpenAiClient)-> Result<AudioResponse>{
-        let final_url =  client.url.to_owned()+Self::ENDPOINT;
-        let res = self.get_response(&client.client,final_url,&client.key).await?;
+        let final_url = client.url_for(Self::ENDPOINT, "", || Some(self.model.clone()));
+        let res = self.get_response(client,final_url).await?;
         process_audio_response(&self.response_format,res).await
     }
+
+    async fn run_with_meta(&self, client:&OpenAiClient)-> Result<WithMeta<AudioResponse>>{
+        let final_url = client.url_for(Self::ENDPOINT, "", || Some(self.model.clone()));
+        let res = self.get_response(client,final_url).await?;
+        let meta = ResponseMeta::from_headers(res.status(), res.headers());
+        let body = process_audio_response(&self.response_format,res).await?;
+        Ok(WithMeta { body, meta })
+    }
 }
 
 
@@ -0,0 +1,128 @@
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use async_trait::async_trait;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+use crate::{OpenAiError, Result};
+
+///credential that is attached to the request
+#[derive(Debug, Clone)]
+pub enum Credential {
+    ///API key, sent as bearer token to OpenAI and in `api-key` header to Azure
+    Key(String),
+    ///token that is always sent as bearer token, like OAuth or Azure AD access token
+    Bearer(String),
+    ///arbitrary header, for gateways with their own auth scheme
+    Header{ name: String, value: String }
+}
+
+impl Credential {
+
+    ///secret part of the credential, that must never appear in logs
+    pub(crate) fn secret(&self) -> &str {
+        match self {
+            Credential::Key(key) |
+            Credential::Bearer(key) |
+            Credential::Header { value: key, .. } => key
+        }
+    }
+
+    pub(crate) fn apply(&self, request: RequestBuilder, azure: bool) -> Result<RequestBuilder> {
+        Ok(match self {
+            Credential::Key(key) if azure => request.header("api-key", key),
+            Credential::Key(key) |
+            Credential::Bearer(key) => request.bearer_auth(key),
+            Credential::Header { name, value } => {
+                let header_name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| OpenAiError::InvalidHeader(name.clone()))?;
+                let header_value = HeaderValue::from_str(value)
+                    .map_err(|_| OpenAiError::InvalidHeader(name.clone()))?;
+                request.header(header_name, header_value)
+            }
+        })
+    }
+}
+
+///Source of credentials for the client. It is asked for credential before every request
+///(including every retry attempt), so it can rotate keys or refresh short-lived tokens.
+/// # Usage example
+/// ```
+/// use openai_req::OpenAiClient;
+/// use openai_req::auth::EnvKey;
+///
+/// let client = OpenAiClient::with_auth(EnvKey::new("OPENAI_API_KEY"));
+/// ```
+#[async_trait]
+pub trait AuthProvider: Debug + Send + Sync {
+    async fn credential(&self) -> Result<Credential>;
+}
+
+///key that never changes, used by client constructors that take key
+#[derive(Debug, Clone)]
+pub struct StaticKey(String);
+
+impl StaticKey {
+    pub fn new(key: &str) -> Self {
+        StaticKey(key.to_string())
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticKey {
+    async fn credential(&self) -> Result<Credential> {
+        Ok(Credential::Key(self.0.clone()))
+    }
+}
+
+///key read from environment variable on every request
+#[derive(Debug, Clone)]
+pub struct EnvKey {
+    variable: String
+}
+
+impl EnvKey {
+    pub fn new(variable: &str) -> Self {
+        EnvKey { variable: variable.to_string() }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for EnvKey {
+    ///fails with `OpenAiError::Credential` if variable is not set
+    async fn credential(&self) -> Result<Credential> {
+        std::env::var(&self.variable)
+            .map(Credential::Key)
+            .map_err(|err| OpenAiError::Credential(format!("{}: {}", self.variable, err)))
+    }
+}
+
+///key stored in a file, like mounted secret. File is re-read when its modification time changes,
+///surrounding whitespace is ignored
+#[derive(Debug)]
+pub struct FileKey {
+    path: PathBuf,
+    cached: tokio::sync::Mutex<Option<(SystemTime, String)>>
+}
+
+impl FileKey {
+    pub fn new(path: PathBuf) -> Self {
+        FileKey { path, cached: tokio::sync::Mutex::new(None) }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for FileKey {
+    async fn credential(&self) -> Result<Credential> {
+        let modified = tokio::fs::metadata(&self.path).await?.modified()?;
+        let mut cached = self.cached.lock().await;
+        match cached.as_ref() {
+            Some((read_at, key)) if *read_at == modified => Ok(Credential::Key(key.clone())),
+            _ => {
+                let key = tokio::fs::read_to_string(&self.path).await?.trim().to_string();
+                *cached = Some((modified, key.clone()));
+                Ok(Credential::Key(key))
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+///Settings for Azure OpenAI hosting.
+///Azure serves models from named deployments, so model requests go to
+///`{resource}/openai/deployments/{deployment}{endpoint}?api-version={version}`,
+///and requests that do not run a model (files, fine-tunes) go to `{resource}/openai{endpoint}?api-version={version}`.
+///Key is sent in `api-key` header instead of bearer auth.
+///
+///Deployment is picked by request's model, then by request's endpoint (for requests without model,
+///like image generation). If neither is mapped, model name itself is used as deployment name.
+/// # Usage example
+/// ```
+/// use openai_req::OpenAiClient;
+/// use openai_req::azure::AzureConfig;
+///
+/// let azure = AzureConfig::new("2024-02-01")
+///     .deployment("gpt-35-turbo", "chat-prod")
+///     .endpoint_deployment("/images/generations", "dalle");
+/// let client = OpenAiClient::azure("{YOUR_API_KEY}", "https://my-resource.openai.azure.com", azure);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AzureConfig {
+    api_version: String,
+    deployments: HashMap<String, String>,
+    endpoint_deployments: HashMap<String, String>
+}
+
+impl AzureConfig {
+
+    pub fn new(api_version: &str) -> Self {
+        AzureConfig {
+            api_version: api_version.to_string(),
+            deployments: HashMap::new(),
+            endpoint_deployments: HashMap::new()
+        }
+    }
+
+    ///requests for `model` are sent to `deployment`
+    pub fn deployment(mut self, model: &str, deployment: &str) -> Self {
+        self.deployments.insert(model.to_string(), deployment.to_string());
+        self
+    }
+
+    ///requests to `endpoint` (like `/images/generations`), that do not name a model, are sent to `deployment`
+    pub fn endpoint_deployment(mut self, endpoint: &str, deployment: &str) -> Self {
+        self.endpoint_deployments.insert(endpoint.to_string(), deployment.to_string());
+        self
+    }
+
+    fn deployment_for<'a>(&'a self, endpoint: &str, model: Option<&'a str>) -> Option<&'a str> {
+        match model {
+            Some(model) => Some(self.deployments.get(model).map(String::as_str).unwrap_or(model)),
+            None => self.endpoint_deployments.get(endpoint).map(String::as_str)
+        }
+    }
+
+    pub(crate) fn url(&self, base: &str, endpoint: &str, path: &str, model: Option<&str>) -> String {
+        let base = base.trim_end_matches('/');
+        match self.deployment_for(endpoint, model) {
+            Some(deployment) => format!("{}/openai/deployments/{}{}{}?api-version={}", base, deployment, endpoint, path, self.api_version),
+            None => format!("{}/openai{}{}?api-version={}", base, endpoint, path, self.api_version)
+        }
+    }
+}
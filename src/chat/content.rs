@@ -0,0 +1,195 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use crate::{OpenAiError, Result};
+
+///Content of chat message: either plain text, or list of typed parts for multimodal models.
+///Text content is serialized as plain string, exactly as before parts were supported.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>)
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl Content {
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Content::Text(text) => text.is_empty(),
+            Content::Parts(parts) => parts.is_empty()
+        }
+    }
+
+    ///text of the content, text parts are joined, other parts are skipped
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts.iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None
+                })
+                .collect::<Vec<&str>>()
+                .join("\n")
+        }
+    }
+
+    ///appends text, used to assemble streamed content
+    pub fn push_str(&mut self, text: &str) {
+        match self {
+            Content::Text(content) => content.push_str(text),
+            Content::Parts(parts) => parts.push(ContentPart::text(text))
+        }
+    }
+}
+
+impl Display for Content {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl From<String> for Content {
+    fn from(value: String) -> Self {
+        Content::Text(value)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(value: &str) -> Self {
+        Content::Text(value.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for Content {
+    fn from(value: Vec<ContentPart>) -> Self {
+        Content::Parts(value)
+    }
+}
+
+impl PartialEq<str> for Content {
+    fn eq(&self, other: &str) -> bool {
+        matches!(self, Content::Text(text) if text == other)
+    }
+}
+
+impl PartialEq<&str> for Content {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+///single part of multimodal message
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text{ text: String },
+    ImageUrl{ image_url: ImageUrl },
+    InputAudio{ input_audio: InputAudio }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ImageUrl {
+    ///web url, or data url with base64 encoded image
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>
+}
+
+///resolution at which model looks at the image, low detail costs fewer tokens
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+    Auto,
+    Low,
+    High
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct InputAudio {
+    ///base64 encoded audio
+    pub data: String,
+    ///`wav` or `mp3`
+    pub format: String
+}
+
+impl ContentPart {
+
+    pub fn text(text: &str) -> Self {
+        ContentPart::Text { text: text.to_string() }
+    }
+
+    ///image available by url
+    pub fn image_url(url: &str, detail: Option<ImageDetail>) -> Self {
+        ContentPart::ImageUrl { image_url: ImageUrl { url: url.to_string(), detail } }
+    }
+
+    ///image sent inline as data url. Format (png, jpeg, gif or webp) is detected from the data,
+    ///other formats fail with `OpenAiError::InvalidParameter`
+    pub fn image_bytes(image: &Bytes, detail: Option<ImageDetail>) -> Result<Self> {
+        let mime = image_mime(image)
+            .ok_or_else(|| unsupported("image", "format is not supported, use png, jpeg, gif or webp"))?;
+        let url = format!("data:{};base64,{}", mime, STANDARD.encode(image));
+        Ok(ContentPart::ImageUrl { image_url: ImageUrl { url, detail } })
+    }
+
+    ///reads local image and sends it inline as data url
+    pub async fn image_file(path: &Path, detail: Option<ImageDetail>) -> Result<Self> {
+        let image = Bytes::from(tokio::fs::read(path).await?);
+        ContentPart::image_bytes(&image, detail)
+    }
+
+    ///audio for audio-capable models. Format (wav or mp3) is detected from the data,
+    ///other formats fail with `OpenAiError::InvalidParameter`
+    pub fn audio_bytes(audio: &Bytes) -> Result<Self> {
+        let format = audio_format(audio)
+            .ok_or_else(|| unsupported("input_audio", "format is not supported, use wav or mp3"))?;
+        Ok(ContentPart::InputAudio {
+            input_audio: InputAudio { data: STANDARD.encode(audio), format: format.to_string() }
+        })
+    }
+
+    ///reads local audio file
+    pub async fn audio_file(path: &Path) -> Result<Self> {
+        let audio = Bytes::from(tokio::fs::read(path).await?);
+        ContentPart::audio_bytes(&audio)
+    }
+}
+
+fn unsupported(param: &str, reason: &str) -> OpenAiError {
+    OpenAiError::InvalidParameter { param: param.to_string(), reason: reason.to_string() }
+}
+
+fn image_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn audio_format(data: &[u8]) -> Option<&'static str> {
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WAVE" {
+        Some("wav")
+    } else if data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0) {
+        Some("mp3")
+    } else {
+        None
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+#[cfg(not(feature = "tokenizer"))]
+use serde_json::json;
+use crate::chat::{ChatRequest, ChatSuccess, Message, Role};
+#[cfg(not(feature = "tokenizer"))]
+use crate::limiter::estimate_tokens;
+use crate::{JsonRequest, OpenAiClient, Result};
+
+///counts prompt tokens of messages
+type TokenCounter = Arc<dyn Fn(&[Message]) -> u64 + Send + Sync>;
+
+///what happens with older turns, that do not fit into context window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    ///older turns are dropped
+    Drop,
+    ///older turns are replaced with summary, written by the same model
+    Summarize
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    message: Message,
+    pinned: bool
+}
+
+///Chat session, that keeps system prompt and history, and makes sure they fit into model's
+///context window. Before each request oldest turns are dropped or summarized, until prompt
+///together with tokens reserved for the answer fits. System prompt, pinned messages and the latest turn
+///are always kept, and assistant tool calls are never separated from their results.
+///
+///Tokens are counted with `tokenizer::count_chat_tokens` if `tokenizer` feature is enabled,
+///otherwise they are estimated with `limiter::estimate_tokens`. Either can be replaced with `counter`.
+/// # Usage example
+/// ```
+/// use openai_req::chat::conversation::{Conversation, Overflow};
+///
+/// let mut conversation = Conversation::new("gpt-4o")
+///     .system("You are a helpful assistant")
+///     .reserve(1_000)
+///     .overflow(Overflow::Summarize);
+/// // let answer = conversation.send(&client, "hello!").await?;
+/// ```
+#[derive(Clone)]
+pub struct Conversation {
+    request: ChatRequest,
+    system: Option<Message>,
+    summary: Option<Message>,
+    history: Vec<Entry>,
+    context_window: u64,
+    reserve: u64,
+    overflow: Overflow,
+    counter: TokenCounter
+}
+
+impl Debug for Conversation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Conversation")
+            .field("request", &self.request)
+            .field("system", &self.system)
+            .field("summary", &self.summary)
+            .field("history", &self.history)
+            .field("context_window", &self.context_window)
+            .field("reserve", &self.reserve)
+            .field("overflow", &self.overflow)
+            .finish()
+    }
+}
+
+impl Conversation {
+
+    ///conversation with given model, context window is taken from `context_window_for`
+    pub fn new(model: &str) -> Self {
+        Conversation::with_request(ChatRequest::with_model_and_messages(model, Vec::new()))
+    }
+
+    ///conversation that uses model and parameters of `request`.
+    ///Leading system message of the request becomes system prompt, other messages become history
+    pub fn with_request(mut request: ChatRequest) -> Self {
+        let mut messages = std::mem::take(&mut request.messages);
+        let system = match messages.first() {
+            Some(message) if message.role == Role::System => Some(messages.remove(0)),
+            _ => None
+        };
+        let request_model = request.model.clone();
+        Conversation {
+            context_window: context_window_for(&request.model),
+            reserve: request.max_completion_tokens.or(request.max_tokens).unwrap_or(1_024),
+            request,
+            system,
+            summary: None,
+            history: messages.into_iter().map(|message| Entry { message, pinned: false }).collect(),
+            overflow: Overflow::Drop,
+            counter: default_counter(&request_model)
+        }
+    }
+
+    pub fn system(mut self, prompt: &str) -> Self {
+        self.system = Some(Message::new(Role::System, prompt));
+        self
+    }
+
+    ///overrides context window size of the model, in tokens
+    pub fn context_window(mut self, context_window: u64) -> Self {
+        self.context_window = context_window;
+        self
+    }
+
+    ///tokens kept free for the answer, defaults to request's `max_completion_tokens`, `max_tokens` or 1024
+    pub fn reserve(mut self, reserve: u64) -> Self {
+        self.reserve = reserve;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    ///replaces token estimate with exact counter
+    pub fn counter(mut self, counter: impl Fn(&[Message]) -> u64 + Send + Sync + 'static) -> Self {
+        self.counter = Arc::new(counter);
+        self
+    }
+
+    ///adds message to history
+    pub fn push(&mut self, message: Message) {
+        self.history.push(Entry { message, pinned: false });
+    }
+
+    ///adds message, that is never dropped or summarized
+    pub fn push_pinned(&mut self, message: Message) {
+        self.history.push(Entry { message, pinned: true });
+    }
+
+    ///all messages, that will be sent with the next request
+    pub fn messages(&self) -> Vec<Message> {
+        self.system.iter()
+            .chain(self.summary.iter())
+            .chain(self.history.iter().map(|entry| &entry.message))
+            .cloned()
+            .collect()
+    }
+
+    ///number of prompt tokens of the current messages, according to conversation's counter
+    pub fn tokens(&self) -> u64 {
+        (self.counter)(&self.messages())
+    }
+
+    ///adds user message, fits history into context window and sends it.
+    ///Answer is added to history
+    pub async fn send(&mut self, client: &OpenAiClient, text: &str) -> Result<ChatSuccess> {
+        self.push(Message::new(Role::User, text));
+        self.run(client).await
+    }
+
+    ///fits history into context window and sends it, answer is added to history.
+    ///Use it after pushing messages, like tool results, manually
+    pub async fn run(&mut self, client: &OpenAiClient) -> Result<ChatSuccess> {
+        self.fit(client).await?;
+        let mut request = self.request.clone();
+        request.messages = self.messages();
+        let response = request.run(client).await?;
+        if let Some(choice) = response.choices.first() {
+            self.push(choice.message.clone());
+        }
+        Ok(response)
+    }
+
+    ///Removes oldest turns until messages fit into context window.
+    ///In `Overflow::Summarize` mode removed turns are summarized by the model first,
+    ///which is the only case when `client` is used
+    pub async fn fit(&mut self, client: &OpenAiClient) -> Result<()> {
+        let budget = self.context_window.saturating_sub(self.reserve);
+        let mut removed = self.trim(budget);
+        if self.overflow == Overflow::Drop {
+            return Ok(())
+        }
+        while !removed.is_empty() {
+            let summary = self.summarize(client, removed).await?;
+            self.summary = Some(Message::new(Role::System, &format!("Summary of the earlier conversation: {}", summary)));
+            //summary takes space too, turns it pushes out go into the next summary
+            removed = self.trim(budget);
+        }
+        Ok(())
+    }
+
+    ///drops oldest removable groups until messages fit into `budget`, returns dropped messages
+    fn trim(&mut self, budget: u64) -> Vec<Message> {
+        let mut removed = Vec::new();
+        while self.tokens() > budget {
+            let groups = groups(&self.history);
+            //latest group is the question being asked, it is never dropped
+            let candidate = groups.iter()
+                .take(groups.len().saturating_sub(1))
+                .find(|range| !self.history[(*range).clone()].iter().any(|entry| entry.pinned))
+                .cloned();
+            match candidate {
+                Some(range) => removed.extend(self.history.drain(range).map(|entry| entry.message)),
+                None => break
+            }
+        }
+        removed
+    }
+
+    async fn summarize(&self, client: &OpenAiClient, removed: Vec<Message>) -> Result<String> {
+        let mut transcript = self.summary.iter()
+            .map(|summary| summary.content.text())
+            .collect::<Vec<String>>();
+        for message in &removed {
+            let role = serde_json::to_value(&message.role).ok()
+                .and_then(|role| role.as_str().map(str::to_string))
+                .unwrap_or_default();
+            let mut line = format!("{}: {}", role, message.content.text());
+            for call in message.tool_calls.iter().flatten() {
+                line.push_str(&format!(" [called {} with {}]", call.function.name, call.function.arguments));
+            }
+            transcript.push(line);
+        }
+        let request = ChatRequest::with_model_and_messages(&self.request.model, vec![
+            Message::new(Role::System, "Summarize the conversation below in a few sentences. \
+                Keep names, facts, decisions and open questions, that may be needed to continue it."),
+            Message::new(Role::User, &transcript.join("\n"))
+        ]);
+        let response = request.run(client).await?;
+        Ok(response.choices.first()
+            .map(|choice| choice.message.content.text())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(not(feature = "tokenizer"))]
+fn default_counter(_model: &str) -> TokenCounter {
+    Arc::new(|messages: &[Message]| estimate_tokens(&json!({ "messages": messages })))
+}
+
+#[cfg(feature = "tokenizer")]
+fn default_counter(model: &str) -> TokenCounter {
+    let model = model.to_string();
+    Arc::new(move |messages: &[Message]| crate::tokenizer::count_chat_tokens(messages, &model))
+}
+
+///splits history into groups that are dropped together:
+///assistant message with tool calls and all tool results that follow it, or single message
+fn groups(history: &[Entry]) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < history.len() {
+        let mut end = start + 1;
+        if history[start].message.tool_calls.is_some() {
+            while end < history.len() && history[end].message.role == Role::Tool {
+                end += 1;
+            }
+        }
+        groups.push(start..end);
+        start = end;
+    }
+    groups
+}
+
+///context window of known models in tokens, 4096 for unknown ones
+pub fn context_window_for(model: &str) -> u64 {
+    const WINDOWS: [(&str, u64); 14] = [
+        ("gpt-4.1", 1_047_576),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4-1106", 128_000),
+        ("gpt-4-0125", 128_000),
+        ("gpt-4-32k", 32_768),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo-instruct", 4_096),
+        ("gpt-3.5-turbo", 16_385),
+        ("gpt-5", 400_000),
+        ("o1-mini", 128_000),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4-mini", 200_000)
+    ];
+    WINDOWS.iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(4_096)
+}
@@ -1,20 +1,325 @@
+pub mod tools;
+pub mod content;
+pub mod conversation;
+
 use async_trait::async_trait;
-use crate::{JsonRequest, Usage};
+use crate::{JsonRequest, OpenAiError, Result, StreamingRequest, Usage};
+use crate::logprobs::ChatLogprobs;
+#[cfg(feature = "schemars")]
+use crate::OpenAiClient;
 use std::collections::HashMap;
-use serde::{Serialize,Deserialize};
+use serde::{Serialize, Deserialize, Deserializer, Serializer};
+use serde::ser::SerializeStruct;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio_stream::{Stream, StreamExt};
+pub use content::{Content, ContentPart, ImageDetail, ImageUrl, InputAudio};
 
-#[derive(Clone, Debug,Serialize,Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     System,
+    #[default]
     User,
     Assistant,
+    ///result of the tool call, requires `tool_call_id`
+    Tool,
+    ///result of the legacy function call, requires `name`
+    Function,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+///Chat message. Content is either text or, for multimodal models, list of parts.
+///Assistant messages that call tools have no content,
+///it is received as empty text and is not sent back when empty and message has `tool_calls`.
+#[derive(Clone, Deserialize, Debug, Default)]
 pub struct Message{
     pub role:Role,
-    pub content:String
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content:Content,
+    #[serde(default)]
+    pub tool_calls:Option<Vec<ToolCall>>,
+    #[serde(default)]
+    pub tool_call_id:Option<String>,
+    #[serde(default)]
+    pub name:Option<String>,
+    ///explanation, sent instead of content, when model refuses to answer with structured output
+    #[serde(default)]
+    pub refusal:Option<String>
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut message = serializer.serialize_struct("Message", 6)?;
+        message.serialize_field("role", &self.role)?;
+        if self.content.is_empty() && self.tool_calls.is_some() {
+            message.skip_field("content")?;
+        } else {
+            message.serialize_field("content", &self.content)?;
+        }
+        match &self.tool_calls {
+            Some(tool_calls) => message.serialize_field("tool_calls", tool_calls)?,
+            None => message.skip_field("tool_calls")?
+        }
+        for (key, value) in [("tool_call_id", &self.tool_call_id), ("name", &self.name), ("refusal", &self.refusal)] {
+            match value {
+                Some(value) => message.serialize_field(key, value)?,
+                None => message.skip_field(key)?
+            }
+        }
+        message.end()
+    }
+}
+
+impl Message {
+
+    pub fn new(role: Role, content: &str) -> Self {
+        Message { role, content: content.into(), ..Default::default() }
+    }
+
+    ///user message with text and other parts, like images
+    pub fn with_parts(role: Role, parts: Vec<ContentPart>) -> Self {
+        Message { role, content: Content::Parts(parts), ..Default::default() }
+    }
+
+    ///result of the tool call with id `tool_call_id`, to be sent back to the model
+    pub fn tool(tool_call_id: &str, content: &str) -> Self {
+        Message {
+            role: Role::Tool,
+            content: content.into(),
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+fn null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Content, D::Error> {
+    Ok(Option::<Content>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+///tool that model may call, currently only functions are supported
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionDefinition
+}
+
+impl Tool {
+
+    ///function tool, `parameters` is JSON schema of function arguments
+    pub fn function(name: &str, description: &str, parameters: Value) -> Self {
+        Tool {
+            kind: "function".to_string(),
+            function: FunctionDefinition {
+                name: name.to_string(),
+                description: Some(description.to_string()),
+                parameters
+            }
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl Tool {
+
+    ///function tool, which arguments are described by type `T`.
+    ///Schema is derived with `schemars`, arguments can be parsed back with `FunctionCall::parse_arguments::<T>`
+    pub fn typed_function<T: schemars::JsonSchema>(name: &str, description: &str) -> Self {
+        Tool::function(name, description, parameters_schema::<T>())
+    }
+}
+
+///JSON schema of type `T`, without meta fields that API does not need
+#[cfg(feature = "schemars")]
+pub fn parameters_schema<T: schemars::JsonSchema>() -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_default();
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("$schema");
+        schema.remove("title");
+    }
+    schema
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub parameters: Value
+}
+
+///controls if and which tool model calls
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(into = "ToolChoiceRepr", try_from = "ToolChoiceRepr")]
+pub enum ToolChoice {
+    ///model does not call tools
+    None,
+    ///model decides whether to call tools
+    Auto,
+    ///model calls at least one tool
+    Required,
+    ///model calls function with given name
+    Function(String)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ToolChoiceRepr {
+    Mode(String),
+    Named {
+        #[serde(rename = "type")]
+        kind: String,
+        function: FunctionName
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct FunctionName {
+    name: String
+}
+
+impl From<ToolChoice> for ToolChoiceRepr {
+    fn from(value: ToolChoice) -> Self {
+        match value {
+            ToolChoice::None => ToolChoiceRepr::Mode("none".to_string()),
+            ToolChoice::Auto => ToolChoiceRepr::Mode("auto".to_string()),
+            ToolChoice::Required => ToolChoiceRepr::Mode("required".to_string()),
+            ToolChoice::Function(name) => ToolChoiceRepr::Named {
+                kind: "function".to_string(),
+                function: FunctionName { name }
+            }
+        }
+    }
+}
+
+impl TryFrom<ToolChoiceRepr> for ToolChoice {
+    type Error = String;
+
+    fn try_from(value: ToolChoiceRepr) -> std::result::Result<Self, Self::Error> {
+        match value {
+            ToolChoiceRepr::Mode(mode) => match mode.as_str() {
+                "none" => Ok(ToolChoice::None),
+                "auto" => Ok(ToolChoice::Auto),
+                "required" => Ok(ToolChoice::Required),
+                _ => Err(format!("unknown tool choice: {}", mode))
+            },
+            ToolChoiceRepr::Named { function, .. } => Ok(ToolChoice::Function(function.name))
+        }
+    }
+}
+
+///tool call requested by the model in assistant message
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionCall
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct FunctionCall {
+    pub name: String,
+    ///arguments as JSON string, exactly as generated by the model
+    pub arguments: String
+}
+
+impl FunctionCall {
+
+    ///parses arguments into type `T`. Model may generate arguments that do not match schema,
+    ///in this case `OpenAiError::Deserialization` with raw arguments is returned
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_str(&self.arguments)
+            .map_err(|source| OpenAiError::Deserialization { body: self.arguments.clone(), source })
+    }
+}
+
+///format that model must use for its answer
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    ///any valid JSON object, prompt itself should ask for JSON
+    JsonObject,
+    ///JSON matching given schema
+    JsonSchema{ json_schema: JsonSchemaFormat }
+}
+
+impl ResponseFormat {
+
+    ///answer must match `schema`. In strict mode, schema must declare all properties as required
+    ///and forbid additional properties, see `strict_schema`
+    pub fn json_schema(name: &str, schema: Value, strict: bool) -> Self {
+        ResponseFormat::JsonSchema {
+            json_schema: JsonSchemaFormat {
+                name: name.to_string(),
+                description: None,
+                schema,
+                strict: Some(strict)
+            }
+        }
+    }
+
+    ///strict schema derived from type `T`, answer can be parsed with `ChatSuccess::parse_content::<T>`
+    #[cfg(feature = "schemars")]
+    pub fn for_type<T: schemars::JsonSchema>() -> Self {
+        let name = T::schema_name().chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect::<String>();
+        ResponseFormat::json_schema(&name, strict_schema(parameters_schema::<T>()), true)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>
+}
+
+///Adapts schema to strict mode requirements: every object forbids additional properties
+///and lists all its properties as required (optional ones stay nullable),
+///numeric formats like `uint8`, that strict mode does not support, are dropped.
+pub fn strict_schema(mut schema: Value) -> Value {
+    make_strict(&mut schema);
+    schema
+}
+
+fn make_strict(schema: &mut Value) {
+    let fields = match schema {
+        Value::Object(fields) => fields,
+        _ => return
+    };
+    if let Some(Value::Object(properties)) = fields.get("properties") {
+        let required = properties.keys().cloned().map(Value::String).collect();
+        fields.insert("required".to_string(), Value::Array(required));
+        fields.insert("additionalProperties".to_string(), Value::Bool(false));
+    }
+    let numeric = |kind: &Value| kind.as_str().is_some_and(|kind| kind == "integer" || kind == "number");
+    let is_numeric = match fields.get("type") {
+        Some(Value::Array(kinds)) => kinds.iter().any(numeric),
+        Some(kind) => numeric(kind),
+        None => false
+    };
+    if is_numeric {
+        fields.remove("format");
+    }
+    //only subschema positions are visited, keys of these maps are names, not schema keywords
+    for key in ["properties", "patternProperties", "$defs", "definitions"] {
+        if let Some(Value::Object(schemas)) = fields.get_mut(key) {
+            schemas.values_mut().for_each(make_strict);
+        }
+    }
+    for key in ["items", "anyOf", "oneOf", "allOf", "not"] {
+        match fields.get_mut(key) {
+            Some(Value::Array(schemas)) => schemas.iter_mut().for_each(make_strict),
+            Some(schema) => make_strict(schema),
+            None => {}
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -24,6 +329,32 @@ pub enum StopSeq{
     Vec(Vec<String>)
 }
 
+///how long reasoning models think before answering, `Minimal` is only supported by gpt-5 models
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High
+}
+
+///processing tier, that serves the request
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceTier {
+    Auto,
+    Default,
+    Flex,
+    Priority
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct StreamOptions {
+    ///sends additional last chunk with usage of the whole request
+    pub include_usage: bool
+}
+
 /// request providing chat completion. Detailed parameter description can be found at
 /// https://platform.openai.com/docs/api-reference/chat
 /// # Usage example
@@ -33,11 +364,41 @@ pub enum StopSeq{
 ///
 /// let messages  = vec!(Message{
 ///      role: Role::User,
-///      content: "hello!".to_string(),
+///      content: "hello!".into(),
+///      ..Default::default()
 ///    });
 ///    let chat_request = ChatRequest::new(messages);
 ///    let response = chat_request.run(&client).await?;
 /// ```
+/// # Streaming example
+/// ```ignore
+/// use openai_req::chat::{ChatRequest, Message, Role};
+/// use openai_req::StreamingRequest;
+/// use tokio_stream::StreamExt;
+///
+///    let chat_request = ChatRequest::new(messages);
+///    let mut stream = chat_request.run_stream(&client).await?;
+///    while let Some(chunk) = stream.next().await {
+///         print!("{}", chunk?.choices[0].delta.content.clone().unwrap_or_default());
+///    }
+/// ```
+/// # Tools example
+/// ```ignore
+/// use openai_req::chat::{ChatRequest, Message, Role, Tool};
+/// use openai_req::JsonRequest;
+/// use serde_json::json;
+///
+///    let weather = Tool::function("get_weather", "current weather in the city", json!({
+///         "type": "object",
+///         "properties": { "city": { "type": "string" } },
+///         "required": ["city"]
+///    }));
+///    let chat_request = ChatRequest::new(messages).tools(vec![weather]);
+///    let response = chat_request.run(&client).await?;
+///    for call in response.choices[0].message.tool_calls.iter().flatten() {
+///         let args: WeatherArgs = call.function.parse_arguments()?;
+///    }
+/// ```
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ChatRequest {
     model:String,
@@ -55,19 +416,63 @@ pub struct ChatRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     presence_penalty: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     frequency_penalty:Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     logit_bias: Option<HashMap<String,f32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    user: Option<String>
+    logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_tier: Option<ServiceTier>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>
 }
 
 
 #[async_trait(?Send)]
 impl JsonRequest<ChatSuccess> for ChatRequest {
     const ENDPOINT: &'static str = "/chat/completions";
+
+    fn validate(&self) -> Result<()> {
+        if self.stream_options.is_some() {
+            return Err(OpenAiError::InvalidParameter {
+                param: "stream_options".to_string(),
+                reason: "only allowed in streaming mode".to_string()
+            })
+        }
+        self.check_model_params()
+    }
+}
+
+impl StreamingRequest<ChatChunk> for ChatRequest {
+    const ENDPOINT: &'static str = "/chat/completions";
+
+    fn validate(&self) -> Result<()> {
+        self.check_model_params()
+    }
 }
 
 impl ChatRequest {
@@ -82,10 +487,23 @@ impl ChatRequest {
             stream: None,
             stop: None,
             max_tokens: None,
+            max_completion_tokens: None,
+            reasoning_effort: None,
+            seed: None,
             presence_penalty: None,
             frequency_penalty: None,
             logit_bias: None,
+            logprobs: None,
+            top_logprobs: None,
             user: None,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            response_format: None,
+            store: None,
+            metadata: None,
+            service_tier: None,
+            stream_options: None,
         }
     }
 
@@ -99,10 +517,23 @@ impl ChatRequest {
             stream: None,
             stop: None,
             max_tokens: None,
+            max_completion_tokens: None,
+            reasoning_effort: None,
+            seed: None,
             presence_penalty: None,
             frequency_penalty: None,
             logit_bias: None,
+            logprobs: None,
+            top_logprobs: None,
             user: None,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            response_format: None,
+            store: None,
+            metadata: None,
+            service_tier: None,
+            stream_options: None,
         }
     }
 
@@ -151,11 +582,31 @@ impl ChatRequest {
         self
     }
 
+    ///not supported by reasoning models, use `max_completion_tokens` instead
     pub fn max_tokens(mut self, max_tokens: u64) -> Self {
         self.max_tokens = Some(max_tokens);
         self
     }
 
+    ///limit of generated tokens, including reasoning tokens
+    pub fn max_completion_tokens(mut self, max_completion_tokens: u64) -> Self {
+        self.max_completion_tokens = Some(max_completion_tokens);
+        self
+    }
+
+    ///only supported by reasoning models
+    pub fn reasoning_effort(mut self, reasoning_effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(reasoning_effort);
+        self
+    }
+
+    ///Makes sampling mostly deterministic: repeated requests with the same seed and parameters
+    ///should return the same result, as long as `system_fingerprint` of response stays the same
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     pub fn presence_penalty(mut self, presence_penalty: f64) -> Self{
         self.presence_penalty= Some(presence_penalty.clamp(-2f64,2f64));
         self
@@ -166,8 +617,22 @@ impl ChatRequest {
         self
     }
 
-    pub fn logit_bias(mut self, logit_bias: HashMap<String, f32>) -> Self {
-        self.logit_bias = Some(logit_bias);
+    ///token id to bias map, or `LogitBias` builder
+    pub fn logit_bias(mut self, logit_bias: impl Into<HashMap<String, f32>>) -> Self {
+        self.logit_bias = Some(logit_bias.into());
+        self
+    }
+
+    ///returns log probabilities of generated tokens in `ChatChoice::logprobs`
+    pub fn logprobs(mut self, logprobs: bool) -> Self {
+        self.logprobs = Some(logprobs);
+        self
+    }
+
+    ///number of most likely alternatives returned for each token, up to 20. Enables `logprobs`
+    pub fn top_logprobs(mut self, top_logprobs: u8) -> Self {
+        self.logprobs = Some(true);
+        self.top_logprobs = Some(top_logprobs.min(20));
         self
     }
 
@@ -176,23 +641,312 @@ impl ChatRequest {
         self
     }
 
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    pub fn add_tool(mut self, tool: Tool) -> Self {
+        self.tools.get_or_insert_with(Vec::new).push(tool);
+        self
+    }
+
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    ///whether model may request several tool calls in one response
+    pub fn parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.parallel_tool_calls = Some(parallel_tool_calls);
+        self
+    }
+
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    ///stores the completion, for use in evals and distillation
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    ///tags of stored completion, up to 16 pairs, keys up to 64 and values up to 512 characters
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn service_tier(mut self, service_tier: ServiceTier) -> Self {
+        self.service_tier = Some(service_tier);
+        self
+    }
+
+    ///Asks API to report usage in the last chunk in streaming mode.
+    ///Request with this option fails validation when sent with `run`
+    pub fn include_usage(mut self, include_usage: bool) -> Self {
+        self.stream_options = Some(StreamOptions { include_usage });
+        self
+    }
+
+    ///Checks parameters against model family, it is done automatically before request is sent,
+    ///as part of `JsonRequest::validate` and `StreamingRequest::validate`.
+    ///Reasoning models (o-series and gpt-5) do not accept `max_tokens`, sampling parameters,
+    ///logprobs and logit bias, other models do not accept `reasoning_effort`.
+    ///Fails with `OpenAiError::InvalidParameter`
+    pub fn check_model_params(&self) -> Result<()> {
+        let invalid = |param: &str, reason: String| Err(OpenAiError::InvalidParameter {
+            param: param.to_string(),
+            reason
+        });
+        if is_reasoning_model(&self.model) {
+            if self.max_tokens.is_some() {
+                return invalid("max_tokens", format!("not supported by {}, use max_completion_tokens", self.model))
+            }
+            let unsupported = [
+                ("temperature", self.temperature.is_some()),
+                ("top_p", self.top_p.is_some()),
+                ("presence_penalty", self.presence_penalty.is_some()),
+                ("frequency_penalty", self.frequency_penalty.is_some()),
+                ("logprobs", self.logprobs.is_some()),
+                ("top_logprobs", self.top_logprobs.is_some()),
+                ("logit_bias", self.logit_bias.is_some())
+            ];
+            if let Some((param, _)) = unsupported.iter().find(|(_, set)| *set) {
+                return invalid(param, format!("not supported by reasoning model {}", self.model))
+            }
+        } else if self.reasoning_effort.is_some() {
+            return invalid("reasoning_effort", format!("{} is not a reasoning model", self.model))
+        }
+        if self.reasoning_effort == Some(ReasoningEffort::Minimal) && !self.model.starts_with("gpt-5") {
+            return invalid("reasoning_effort", format!("minimal effort is not supported by {}", self.model))
+        }
+        if self.max_tokens.is_some() && self.max_completion_tokens.is_some() {
+            return invalid("max_tokens", "can not be combined with max_completion_tokens".to_string())
+        }
+        if self.parallel_tool_calls.is_some() && self.tools.is_none() {
+            return invalid("parallel_tool_calls", "only allowed when tools are specified".to_string())
+        }
+        if let Some(metadata) = &self.metadata {
+            if metadata.len() > 16 {
+                return invalid("metadata", "up to 16 pairs are allowed".to_string())
+            }
+            let too_long = metadata.iter()
+                .find(|(key, value)| key.chars().count() > 64 || value.chars().count() > 512);
+            if let Some((key, _)) = too_long {
+                return invalid("metadata", format!("key or value of {} is too long", key))
+            }
+        }
+        Ok(())
+    }
+
+    ///Sends request with strict JSON schema derived from `T` as response format,
+    ///and parses answer into `T`. Refusal and answer cut by token limit are returned as
+    ///`OpenAiError::Refusal` and `OpenAiError::Truncated`
+    #[cfg(feature = "schemars")]
+    pub async fn run_typed<T: schemars::JsonSchema + DeserializeOwned>(&self, client: &OpenAiClient) -> Result<T> {
+        let request = self.clone().response_format(ResponseFormat::for_type::<T>());
+        request.run(client).await?.parse_content()
+    }
+
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ChatChoice {
     pub index: u16,
     pub message: Message,
-    pub finish_reason: String
+    pub finish_reason: String,
+    ///present when `logprobs` is requested
+    #[serde(default)]
+    pub logprobs: Option<ChatLogprobs>
 }
 
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct ChatSuccess {
     pub id: String,
     pub object: String,
     pub created: u64,
     pub choices: Vec<ChatChoice>,
-    pub usage:Usage
+    pub usage:Usage,
+    ///backend configuration, that served the request. Results for the same `seed`
+    ///are only reproducible while it stays the same
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    #[serde(default)]
+    pub service_tier: Option<String>
 }
 
+impl ChatSuccess {
+
+    ///parses content of the first choice as JSON, for requests with JSON response format.
+    ///Fails with `OpenAiError::Refusal` if model refused to answer,
+    ///with `OpenAiError::Truncated` if answer was cut by token limit,
+    ///and with `OpenAiError::NoChoices` if response has no choices
+    pub fn parse_content<T: DeserializeOwned>(&self) -> Result<T> {
+        let choice = match self.choices.first() {
+            Some(choice) => choice,
+            None => return Err(OpenAiError::NoChoices)
+        };
+        if let Some(refusal) = &choice.message.refusal {
+            return Err(OpenAiError::Refusal(refusal.clone()))
+        }
+        if choice.finish_reason == "length" {
+            return Err(OpenAiError::Truncated(choice.message.content.text()))
+        }
+        let content = choice.message.content.text();
+        serde_json::from_str(&content)
+            .map_err(|source| OpenAiError::Deserialization { body: content, source })
+    }
+
+    ///applies single streamed chunk to this response:
+    ///appends content deltas to choice with the same index, and takes over finish reason
+    pub fn merge_chunk(&mut self, chunk: ChatChunk) {
+        self.id = chunk.id;
+        self.created = chunk.created;
+        if let Some(usage) = chunk.usage {
+            self.usage = usage;
+        }
+        if chunk.system_fingerprint.is_some() {
+            self.system_fingerprint = chunk.system_fingerprint;
+        }
+        if chunk.service_tier.is_some() {
+            self.service_tier = chunk.service_tier;
+        }
+        for delta_choice in chunk.choices {
+            let position = self.choices.iter().position(|c| c.index == delta_choice.index);
+            let choice = match position {
+                Some(position) => &mut self.choices[position],
+                None => {
+                    self.choices.push(ChatChoice {
+                        index: delta_choice.index,
+                        message: Message { role: Role::Assistant, ..Default::default() },
+                        finish_reason: String::new(),
+                        logprobs: None
+                    });
+                    self.choices.last_mut().unwrap()
+                }
+            };
+            if let Some(role) = delta_choice.delta.role {
+                choice.message.role = role;
+            }
+            if let Some(content) = delta_choice.delta.content {
+                choice.message.content.push_str(&content);
+            }
+            if let Some(refusal) = delta_choice.delta.refusal {
+                choice.message.refusal.get_or_insert_with(String::new).push_str(&refusal);
+            }
+            for delta_call in delta_choice.delta.tool_calls.into_iter().flatten() {
+                let calls = choice.message.tool_calls.get_or_insert_with(Vec::new);
+                while calls.len() <= delta_call.index {
+                    calls.push(ToolCall::default());
+                }
+                let call = &mut calls[delta_call.index];
+                if let Some(id) = delta_call.id {
+                    call.id = id;
+                }
+                if let Some(kind) = delta_call.kind {
+                    call.kind = kind;
+                }
+                if let Some(function) = delta_call.function {
+                    if let Some(name) = function.name {
+                        call.function.name.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        call.function.arguments.push_str(&arguments);
+                    }
+                }
+            }
+            if let Some(logprobs) = delta_choice.logprobs {
+                choice.logprobs.get_or_insert_with(ChatLogprobs::default).extend(logprobs);
+            }
+            if let Some(finish_reason) = delta_choice.finish_reason {
+                choice.finish_reason = finish_reason;
+            }
+        }
+        self.choices.sort_by_key(|c| c.index);
+    }
 
+    ///consumes stream returned by `run_stream` and folds all deltas into full response.
+    ///Usage is only reported by API in streaming mode when requested with `include_usage`,
+    ///otherwise it is left zeroed.
+    pub async fn collect_stream<S>(mut stream: S) -> Result<ChatSuccess>
+        where S: Stream<Item=Result<ChatChunk>> + Unpin
+    {
+        let mut success = ChatSuccess::default();
+        while let Some(chunk) = stream.next().await {
+            success.merge_chunk(chunk?);
+        }
+        Ok(success)
+    }
+}
+
+impl FromIterator<ChatChunk> for ChatSuccess {
+    fn from_iter<T: IntoIterator<Item=ChatChunk>>(iter: T) -> Self {
+        let mut success = ChatSuccess::default();
+        for chunk in iter {
+            success.merge_chunk(chunk);
+        }
+        success
+    }
+}
+
+///part of the message, received in streaming mode
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ChatDelta {
+    pub role: Option<Role>,
+    pub content: Option<String>,
+    #[serde(default)]
+    pub refusal: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>
+}
+
+///part of the tool call, received in streaming mode.
+///Id and name come in first part, arguments are split over the following ones
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub function: Option<FunctionCallDelta>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChatChunkChoice {
+    pub index: u16,
+    pub delta: ChatDelta,
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub logprobs: Option<ChatLogprobs>
+}
+
+///single server-sent event of streamed chat completion
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChatChunk {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub choices: Vec<ChatChunkChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    #[serde(default)]
+    pub service_tier: Option<String>
+}
+
+///o-series and gpt-5 models, except non-reasoning gpt-5 chat model
+fn is_reasoning_model(model: &str) -> bool {
+    ["o1", "o3", "o4", "gpt-5"].iter().any(|prefix| model.starts_with(prefix))
+        && !model.starts_with("gpt-5-chat")
+}
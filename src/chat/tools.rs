@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use futures_util::future::join_all;
+#[cfg(feature = "schemars")]
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::chat::{ChatRequest, ChatSuccess, Message, Tool, ToolCall};
+use crate::{JsonRequest, OpenAiClient, OpenAiError, Result};
+
+type ToolFuture = Pin<Box<dyn Future<Output=String> + Send>>;
+
+///takes raw arguments generated by the model, returns content of the tool message
+type Handler = Arc<dyn Fn(String) -> ToolFuture + Send + Sync>;
+
+#[derive(Clone)]
+struct RegisteredTool {
+    definition: Tool,
+    handler: Handler
+}
+
+impl Debug for RegisteredTool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredTool").field("definition", &self.definition).finish()
+    }
+}
+
+///Set of tools, that model can call, together with async handlers that execute them.
+///Used by `ChatRequest::run_with_tools`, which sends tool definitions to the model,
+///runs requested calls and sends results back, until model gives final answer.
+///
+///Handler errors, unknown tool names and malformed arguments are not fatal,
+///they are reported to the model as tool results, so it can correct itself.
+/// # Usage example
+/// ```
+/// use openai_req::chat::tools::ToolRegistry;
+/// use serde_json::{json, Value};
+///
+/// let registry = ToolRegistry::new()
+///     .register("get_weather", "current weather in the city", json!({
+///         "type": "object",
+///         "properties": { "city": { "type": "string" } },
+///         "required": ["city"]
+///     }), |args: Value| async move {
+///         Ok::<String, String>(format!("sunny in {}", args["city"]))
+///     })
+///     .max_iterations(5);
+/// ```
+/// # Typed tool example
+/// with `schemars` feature, arguments schema is derived from the type
+/// ```ignore
+/// #[derive(Deserialize, JsonSchema)]
+/// struct WeatherArgs {
+///     ///name of the city
+///     city: String
+/// }
+///
+/// let registry = ToolRegistry::new()
+///     .register_typed("get_weather", "current weather in the city", |args: WeatherArgs| async move {
+///         Ok::<String, String>(format!("sunny in {}", args.city))
+///     });
+/// ```
+#[derive(Clone, Debug)]
+pub struct ToolRegistry {
+    tools: HashMap<String, RegisteredTool>,
+    max_iterations: usize
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        ToolRegistry {
+            tools: HashMap::new(),
+            max_iterations: 10
+        }
+    }
+}
+
+impl ToolRegistry {
+
+    ///empty registry, that allows up to 10 model requests per run
+    pub fn new() -> Self {
+        ToolRegistry::default()
+    }
+
+    ///registers function tool. `parameters` is JSON schema of arguments,
+    ///handler receives parsed arguments and returns content of the tool message
+    pub fn register<F, Fut, E>(self, name: &str, description: &str, parameters: Value, handler: F) -> Self
+        where F: Fn(Value) -> Fut + Send + Sync + 'static,
+              Fut: Future<Output=std::result::Result<String, E>> + Send + 'static,
+              E: Display
+    {
+        let handler = Arc::new(handler);
+        self.register_raw(Tool::function(name, description, parameters), Arc::new(move |arguments: String| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                match serde_json::from_str::<Value>(&arguments) {
+                    Ok(arguments) => tool_result(handler(arguments).await),
+                    Err(err) => format!("error: arguments are not valid JSON: {}", err)
+                }
+            }) as ToolFuture
+        }))
+    }
+
+    ///registers function tool, which arguments are described by type `T`.
+    ///Schema is derived from the type, and arguments are deserialized into it before calling handler.
+    ///If arguments do not match the type, handler is not called, and error is sent back to the model.
+    #[cfg(feature = "schemars")]
+    pub fn register_typed<T, F, Fut, E>(self, name: &str, description: &str, handler: F) -> Self
+        where T: schemars::JsonSchema + DeserializeOwned + Send + 'static,
+              F: Fn(T) -> Fut + Send + Sync + 'static,
+              Fut: Future<Output=std::result::Result<String, E>> + Send + 'static,
+              E: Display
+    {
+        let handler = Arc::new(handler);
+        self.register_raw(Tool::typed_function::<T>(name, description), Arc::new(move |arguments: String| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                match serde_json::from_str::<T>(&arguments) {
+                    Ok(arguments) => tool_result(handler(arguments).await),
+                    Err(err) => format!("error: arguments do not match schema: {}", err)
+                }
+            }) as ToolFuture
+        }))
+    }
+
+    fn register_raw(mut self, definition: Tool, handler: Handler) -> Self {
+        self.tools.insert(definition.function.name.clone(), RegisteredTool { definition, handler });
+        self
+    }
+
+    ///maximum number of requests to the model in one run, guards against endless tool call loops
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    ///definitions of all registered tools, as sent to the model
+    pub fn definitions(&self) -> Vec<Tool> {
+        let mut definitions: Vec<Tool> = self.tools.values()
+            .map(|tool| tool.definition.clone())
+            .collect();
+        definitions.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        definitions
+    }
+
+    ///executes single tool call, and returns message with its result
+    pub async fn call(&self, call: &ToolCall) -> Message {
+        let content = match self.tools.get(&call.function.name) {
+            Some(tool) => (tool.handler)(call.function.arguments.clone()).await,
+            None => format!("error: unknown tool {}", call.function.name)
+        };
+        Message::tool(&call.id, &content)
+    }
+}
+
+fn tool_result<E: Display>(result: std::result::Result<String, E>) -> String {
+    match result {
+        Ok(content) => content,
+        Err(err) => format!("error: {}", err)
+    }
+}
+
+///outcome of `ChatRequest::run_with_tools`
+#[derive(Clone, Debug)]
+pub struct ToolRun {
+    ///final response of the model, that has no more tool calls
+    pub response: ChatSuccess,
+    ///all messages of the conversation: initial ones, tool calls, tool results and final answer
+    pub transcript: Vec<Message>
+}
+
+impl ChatRequest {
+
+    ///Sends request with tools from `registry`, executes tool calls requested by the model
+    ///and sends results back, until model responds without tool calls.
+    ///Parallel tool calls are executed concurrently.
+    ///Fails with `OpenAiError::ToolIterations` if model still calls tools after
+    ///`max_iterations` requests.
+    pub async fn run_with_tools(&self, client: &OpenAiClient, registry: &ToolRegistry) -> Result<ToolRun> {
+        let mut request = self.clone();
+        let known = request.tools.iter().flatten()
+            .map(|tool| tool.function.name.clone())
+            .collect::<Vec<String>>();
+        for tool in registry.definitions() {
+            if !known.contains(&tool.function.name) {
+                request = request.add_tool(tool);
+            }
+        }
+        for _ in 0..registry.max_iterations {
+            let response = request.run(client).await?;
+            let message = match response.choices.first() {
+                Some(choice) => choice.message.clone(),
+                None => return Ok(ToolRun { response, transcript: request.messages })
+            };
+            let calls = message.tool_calls.clone().unwrap_or_default();
+            request.push_message(message);
+            if calls.is_empty() {
+                return Ok(ToolRun { response, transcript: request.messages })
+            }
+            let results = join_all(calls.iter().map(|call| registry.call(call))).await;
+            for result in results {
+                request.push_message(result);
+            }
+        }
+        Err(OpenAiError::ToolIterations(registry.max_iterations))
+    }
+}
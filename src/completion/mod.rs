@@ -1,7 +1,9 @@
 use async_trait::async_trait;
-use crate::{Input, JsonRequest, Usage};
+use crate::{Input, JsonRequest, Result, StreamingRequest, Usage};
+use crate::logprobs::CompletionLogprobs;
 use std::collections::HashMap;
 use serde::{Serialize,Deserialize};
+use tokio_stream::{Stream, StreamExt};
 
 ///text completion request
 ///detailed description of params at https://platform.openai.com/docs/api-reference/completions
@@ -15,6 +17,21 @@ use serde::{Serialize,Deserialize};
 ///         CompletionRequest::new("long long time ago".into());
 ///     let response = completion_request.run(&client).await?;
 /// ```
+/// # Streaming example
+///```ignore
+///    use openai_req::completion::CompletionRequest;
+///    use openai_req::StreamingRequest;
+///    use tokio_stream::StreamExt;
+///
+///    let completion_request =
+///         CompletionRequest::new("long long time ago".into());
+///    let mut stream = completion_request.run_stream(&client).await?;
+///    while let Some(chunk) = stream.next().await {
+///         for choice in chunk?.choices {
+///             println!("{}: {}", choice.index, choice.text);
+///         }
+///    }
+/// ```
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CompletionRequest {
     model: String,
@@ -55,6 +72,10 @@ impl JsonRequest<CompletionSuccess> for CompletionRequest {
     const ENDPOINT: &'static str = "/completions";
 }
 
+impl StreamingRequest<CompletionChunk> for CompletionRequest {
+    const ENDPOINT: &'static str = "/completions";
+}
+
 impl CompletionRequest {
     pub fn new(prompt: Input) -> CompletionRequest {
         CompletionRequest {
@@ -127,6 +148,7 @@ impl CompletionRequest {
         self
     }
 
+    ///number of most likely alternatives returned for each token, up to 5
     pub fn set_logprobs(&mut self, logprobs: u32) -> &mut Self {
         self.logprobs = Some(logprobs);
         self
@@ -157,8 +179,9 @@ impl CompletionRequest {
         self
     }
 
-    pub fn set_logit_bias(mut self, logit_bias: HashMap<String, f32>) -> Self {
-        self.logit_bias = Some(logit_bias);
+    ///token id to bias map, or `LogitBias` builder
+    pub fn set_logit_bias(mut self, logit_bias: impl Into<HashMap<String, f32>>) -> Self {
+        self.logit_bias = Some(logit_bias.into());
         self
     }
 
@@ -172,11 +195,12 @@ impl CompletionRequest {
 pub  struct CompletionChoice {
     pub text: String,
     pub index: i64,
-    pub logprobs: Option<u32>,
+    ///present when `logprobs` is requested
+    pub logprobs: Option<CompletionLogprobs>,
     pub finish_reason: String,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub  struct CompletionSuccess {
     pub id: String,
     pub object: String,
@@ -185,3 +209,85 @@ pub  struct CompletionSuccess {
     pub choices: Vec<CompletionChoice>,
     pub usage: Usage,
 }
+
+impl CompletionSuccess {
+
+    ///applies single streamed chunk to this response.
+    ///When `n` > 1 fragments of different choices arrive interleaved,
+    ///each fragment is appended to the choice with the same index
+    pub fn merge_chunk(&mut self, chunk: CompletionChunk) {
+        self.id = chunk.id;
+        self.object = chunk.object;
+        self.created = chunk.created;
+        self.model = chunk.model;
+        if let Some(usage) = chunk.usage {
+            self.usage = usage;
+        }
+        for fragment in chunk.choices {
+            let position = self.choices.iter().position(|c| c.index == fragment.index);
+            let choice = match position {
+                Some(position) => &mut self.choices[position],
+                None => {
+                    self.choices.push(CompletionChoice {
+                        text: String::new(),
+                        index: fragment.index,
+                        logprobs: None,
+                        finish_reason: String::new()
+                    });
+                    self.choices.last_mut().unwrap()
+                }
+            };
+            choice.text.push_str(&fragment.text);
+            if let Some(logprobs) = fragment.logprobs {
+                choice.logprobs.get_or_insert_with(CompletionLogprobs::default).extend(logprobs);
+            }
+            if let Some(finish_reason) = fragment.finish_reason {
+                choice.finish_reason = finish_reason;
+            }
+        }
+        self.choices.sort_by_key(|c| c.index);
+    }
+
+    ///consumes stream returned by `run_stream` and folds all fragments into full response.
+    ///Usage is not reported by API in streaming mode, so it is left zeroed.
+    pub async fn collect_stream<S>(mut stream: S) -> Result<CompletionSuccess>
+        where S: Stream<Item=Result<CompletionChunk>> + Unpin
+    {
+        let mut success = CompletionSuccess::default();
+        while let Some(chunk) = stream.next().await {
+            success.merge_chunk(chunk?);
+        }
+        Ok(success)
+    }
+}
+
+impl FromIterator<CompletionChunk> for CompletionSuccess {
+    fn from_iter<T: IntoIterator<Item=CompletionChunk>>(iter: T) -> Self {
+        let mut success = CompletionSuccess::default();
+        for chunk in iter {
+            success.merge_chunk(chunk);
+        }
+        success
+    }
+}
+
+///text fragment of single choice, received in streaming mode
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CompletionChunkChoice {
+    pub text: String,
+    pub index: i64,
+    pub logprobs: Option<CompletionLogprobs>,
+    pub finish_reason: Option<String>,
+}
+
+///single server-sent event of streamed completion
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<CompletionChunkChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>
+}
@@ -0,0 +1,146 @@
+use futures_util::{stream, StreamExt, TryStreamExt};
+use crate::embeddings::{count_tokens, EmbeddingRequest, EmbeddingResponse, Usage};
+use crate::retry::{self, RetryPolicy};
+use crate::{Input, JsonRequest, OpenAiClient, OpenAiError, Result};
+
+///Embeds any number of texts, splitting them into requests that stay under API limits
+///of inputs and tokens per request. Batches are sent concurrently, failed batches are repeated
+///according to retry policy, and results are returned in the order of texts.
+///
+///Tokens are counted with the model's tokenizer if `tokenizer` feature is enabled,
+///otherwise they are estimated with `limiter::estimate_tokens`.
+/// # Usage example
+/// ```ignore
+/// use openai_req::embeddings::batch::EmbeddingBatcher;
+///
+/// let batcher = EmbeddingBatcher::new("text-embedding-3-small").concurrency(8);
+/// let response = batcher.embed_all(&client, documents).await?;
+/// assert_eq!(response.data.len(), documents_count);
+/// ```
+#[derive(Clone, Debug)]
+pub struct EmbeddingBatcher {
+    request: EmbeddingRequest,
+    max_inputs: usize,
+    max_tokens: u64,
+    concurrency: usize,
+    retry: RetryPolicy
+}
+
+impl Default for EmbeddingBatcher {
+    ///batches for model of `EmbeddingRequest::new`, with API limits of 2048 inputs
+    ///and 300 000 tokens per request, 4 concurrent requests and default retry policy
+    fn default() -> Self {
+        EmbeddingBatcher::with_request(EmbeddingRequest::new(Input::StringArray(Vec::new())))
+    }
+}
+
+impl EmbeddingBatcher {
+
+    pub fn new(model: &str) -> Self {
+        EmbeddingBatcher::with_request(EmbeddingRequest::with_model(model.to_string(), Input::StringArray(Vec::new())))
+    }
+
+    ///batches, that are sent with model and parameters of `request`, its input is ignored
+    pub fn with_request(request: EmbeddingRequest) -> Self {
+        EmbeddingBatcher {
+            request,
+            max_inputs: 2048,
+            max_tokens: 300_000,
+            concurrency: 4,
+            retry: RetryPolicy::default()
+        }
+    }
+
+    ///maximum number of texts in a single request
+    pub fn max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs.max(1);
+        self
+    }
+
+    ///maximum number of tokens in a single request. Text longer than that is still sent,
+    ///alone in its batch
+    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    ///number of requests sent at the same time
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    ///Policy for repeating batches, which response failed after status was received.
+    ///Error statuses and connection errors are repeated by client's retry policy instead,
+    ///so only attempts, delays and `retry_transport_errors` of this policy are used
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    ///splits texts into batches, that respect input and token limits, keeping their order
+    pub fn batches(&self, texts: Vec<String>) -> Vec<Vec<String>> {
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut tokens = 0;
+        for text in texts {
+            let text_tokens = count_tokens(&self.request.model, &text);
+            let full = batch.len() >= self.max_inputs || tokens + text_tokens > self.max_tokens;
+            if full && !batch.is_empty() {
+                batches.push(std::mem::take(&mut batch));
+                tokens = 0;
+            }
+            tokens += text_tokens;
+            batch.push(text);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    ///Embeds all texts. Embedding of each text has `index` of that text in `texts`,
+    ///and usage is summed over all requests. Fails if any batch fails after all retries
+    pub async fn embed_all(&self, client: &OpenAiClient, texts: Vec<String>) -> Result<EmbeddingResponse> {
+        let mut offset = 0;
+        let requests = self.batches(texts).into_iter()
+            .map(|batch| {
+                let batch_offset = offset;
+                offset += batch.len();
+                let mut request = self.request.clone();
+                request.input = Input::StringArray(batch);
+                (batch_offset, request)
+            })
+            .collect::<Vec<_>>();
+        let responses = stream::iter(requests)
+            .map(|(offset, request)| async move {
+                let response = retry::repeat(&self.retry, || request.run(client)).await?;
+                Ok::<_, OpenAiError>((offset, response))
+            })
+            .buffer_unordered(self.concurrency)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut merged = EmbeddingResponse {
+            object: "list".to_string(),
+            data: Vec::new(),
+            model: self.request.model.clone(),
+            usage: Usage { prompt_tokens: 0, total_tokens: 0 }
+        };
+        for (offset, response) in responses {
+            merged.model = response.model;
+            merged.usage.prompt_tokens += response.usage.prompt_tokens;
+            merged.usage.total_tokens += response.usage.total_tokens;
+            merged.data.extend(response.data.into_iter().map(|mut embedding| {
+                embedding.index += offset as i64;
+                embedding
+            }));
+        }
+        merged.data.sort_by_key(|embedding| embedding.index);
+        Ok(merged)
+    }
+}
+
+///embeds all texts with default `EmbeddingBatcher`, see `EmbeddingBatcher::embed_all`
+pub async fn embed_all(client: &OpenAiClient, texts: Vec<String>) -> Result<EmbeddingResponse> {
+    EmbeddingBatcher::default().embed_all(client, texts).await
+}
@@ -0,0 +1,762 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::embeddings::{EmbeddingRequest, EmbeddingResponse};
+use crate::{OpenAiError, Result};
+
+///how similarity of vectors is measured
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Metric {
+    ///cosine of the angle between vectors, from -1 to 1, higher is closer
+    Cosine,
+    ///dot product, higher is closer. Same as cosine for normalized vectors, but faster
+    Dot,
+    ///euclidean distance, lower is closer
+    Euclidean
+}
+
+///Parameters of approximate search graph, see HNSW paper for details.
+///Higher values give better recall, at the cost of memory and speed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HnswParams {
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize
+}
+
+impl Default for HnswParams {
+    ///16 links per node, 200 candidates when building and 50 when searching
+    fn default() -> Self {
+        HnswParams { m: 16, ef_construction: 200, ef_search: 50 }
+    }
+}
+
+impl HnswParams {
+
+    ///links per node on upper layers, layer 0 has twice as many
+    pub fn m(mut self, m: usize) -> Self {
+        self.m = m.max(2);
+        self
+    }
+
+    ///candidates considered when node is linked into the graph
+    pub fn ef_construction(mut self, ef_construction: usize) -> Self {
+        self.ef_construction = ef_construction.max(1);
+        self
+    }
+
+    ///candidates considered when searching, at least `k` are always considered
+    pub fn ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search.max(1);
+        self
+    }
+}
+
+///single search result, ordered from the closest
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit<'a, M> {
+    pub id: &'a str,
+    ///similarity for `Cosine` and `Dot`, distance for `Euclidean`
+    pub score: f32,
+    pub metadata: &'a M
+}
+
+///In-memory store of vectors with ids and metadata, searchable for `k` closest vectors.
+///Search is exact by default, and approximate with `hnsw`, which is much faster on large indexes.
+///Inserting vector with existing id replaces it.
+///
+///Index can be saved to a binary file with vectors and a JSON sidecar with ids and metadata,
+///see `save` and `load`.
+/// # Usage example
+/// ```
+/// use openai_req::embeddings::index::{Metric, VectorIndex};
+/// use serde_json::json;
+///
+/// let mut index = VectorIndex::new(3, Metric::Cosine);
+/// index.insert("cats", vec![1.0, 0.1, 0.0], json!({"title": "About cats"}))?;
+/// index.insert("cars", vec![0.0, 0.2, 1.0], json!({"title": "About cars"}))?;
+/// let hits = index.search(&[0.9, 0.2, 0.1], 1)?;
+/// assert_eq!(hits[0].id, "cats");
+/// # Ok::<(), openai_req::OpenAiError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct VectorIndex<M = Value> {
+    model: Option<String>,
+    dimensions: usize,
+    metric: Metric,
+    normalize: bool,
+    ids: Vec<String>,
+    positions: HashMap<String, usize>,
+    vectors: Vectors,
+    metadata: Vec<M>,
+    hnsw: Option<Hnsw>
+}
+
+impl<M> VectorIndex<M> {
+
+    ///empty index with exact search
+    pub fn new(dimensions: usize, metric: Metric) -> Self {
+        VectorIndex {
+            model: None,
+            dimensions,
+            metric,
+            normalize: false,
+            ids: Vec::new(),
+            positions: HashMap::new(),
+            vectors: Vectors::Owned(Vec::new()),
+            metadata: Vec::new(),
+            hnsw: None
+        }
+    }
+
+    ///embedding model of the vectors, it is checked when index is loaded
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    ///Scales inserted vectors and queries to unit length. OpenAI embeddings are already normalized,
+    ///so this is only needed for other vectors. Should be set before vectors are inserted
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    ///Switches to approximate search. Graph is built from vectors already in the index,
+    ///and updated on every insert
+    pub fn hnsw(mut self, params: HnswParams) -> Self {
+        let mut hnsw = Hnsw::new(params);
+        for node in 0..self.ids.len() {
+            hnsw.insert(node, |a, b| self.distance(self.vector(a), self.vector(b)));
+        }
+        self.hnsw = Some(hnsw);
+        self
+    }
+
+    pub fn embedding_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    ///stored vector and metadata of the id
+    pub fn get(&self, id: &str) -> Option<(&[f32], &M)> {
+        let position = *self.positions.get(id)?;
+        Some((self.vector(position), &self.metadata[position]))
+    }
+
+    ///ids in insertion order
+    pub fn ids(&self) -> impl Iterator<Item=&str> {
+        self.ids.iter().map(String::as_str)
+    }
+
+    ///Adds vector, or replaces vector and metadata of existing id.
+    ///Fails with `OpenAiError::DimensionMismatch` if vector has wrong length
+    pub fn insert(&mut self, id: &str, mut vector: Vec<f32>, metadata: M) -> Result<()> {
+        self.check_dimensions(&vector)?;
+        if self.normalize {
+            normalize(&mut vector);
+        }
+        let position = match self.positions.get(id) {
+            Some(&position) => {
+                self.vectors.to_mut()[position * self.dimensions..(position + 1) * self.dimensions].copy_from_slice(&vector);
+                self.metadata[position] = metadata;
+                position
+            }
+            None => {
+                let position = self.ids.len();
+                self.ids.push(id.to_string());
+                self.positions.insert(id.to_string(), position);
+                self.vectors.to_mut().extend_from_slice(&vector);
+                self.metadata.push(metadata);
+                position
+            }
+        };
+        if let Some(mut hnsw) = self.hnsw.take() {
+            hnsw.insert(position, |a, b| self.distance(self.vector(a), self.vector(b)));
+            self.hnsw = Some(hnsw);
+        }
+        Ok(())
+    }
+
+    ///Inserts embeddings of the response. Entries give id and metadata of each input,
+    ///and are matched to embeddings by their `index`. Model of the response becomes model of the index,
+    ///if it was not set. Nothing is inserted, if number of entries differs from number of embeddings,
+    ///or if any embedding has wrong number of dimensions
+    pub fn insert_response(&mut self, response: EmbeddingResponse, entries: Vec<(String, M)>) -> Result<()> {
+        if entries.len() != response.data.len() {
+            return Err(OpenAiError::InvalidParameter {
+                param: "entries".to_string(),
+                reason: format!("{} entries given for {} embeddings", entries.len(), response.data.len())
+            })
+        }
+        if let Some(embedding) = response.data.iter().find(|embedding| embedding.embedding.len() != self.dimensions) {
+            return Err(OpenAiError::DimensionMismatch { expected: self.dimensions, actual: embedding.embedding.len() })
+        }
+        self.model.get_or_insert(response.model);
+        let mut data = response.data;
+        data.sort_by_key(|embedding| embedding.index);
+        for (embedding, (id, metadata)) in data.into_iter().zip(entries) {
+            self.insert(&id, embedding.embedding, metadata)?;
+        }
+        Ok(())
+    }
+
+    ///`k` closest vectors to the query, approximate if index uses `hnsw`
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit<'_, M>>> {
+        let query = self.prepare_query(query)?;
+        let found = match &self.hnsw {
+            Some(hnsw) => hnsw.search(|node| self.distance(&query, self.vector(node)), k),
+            None => self.brute_force(&query, k)
+        };
+        Ok(self.hits(found))
+    }
+
+    ///`k` closest vectors to the query, compared with every stored vector
+    pub fn search_exact(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit<'_, M>>> {
+        let query = self.prepare_query(query)?;
+        Ok(self.hits(self.brute_force(&query, k)))
+    }
+
+    fn prepare_query(&self, query: &[f32]) -> Result<Vec<f32>> {
+        self.check_dimensions(query)?;
+        let mut query = query.to_vec();
+        if self.normalize {
+            normalize(&mut query);
+        }
+        Ok(query)
+    }
+
+    fn check_dimensions(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimensions {
+            return Err(OpenAiError::DimensionMismatch { expected: self.dimensions, actual: vector.len() })
+        }
+        Ok(())
+    }
+
+    fn vector(&self, position: usize) -> &[f32] {
+        &self.vectors.as_slice()[position * self.dimensions..(position + 1) * self.dimensions]
+    }
+
+    ///distance used for ordering, lower is closer for every metric
+    fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self.metric {
+            Metric::Cosine => 1.0 - cosine(a, b),
+            Metric::Dot => -dot(a, b),
+            Metric::Euclidean => a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+        }
+    }
+
+    fn score(&self, distance: f32) -> f32 {
+        match self.metric {
+            Metric::Cosine => 1.0 - distance,
+            Metric::Dot => -distance,
+            Metric::Euclidean => distance.sqrt()
+        }
+    }
+
+    fn brute_force(&self, query: &[f32], k: usize) -> Vec<Candidate> {
+        let mut closest = BinaryHeap::with_capacity(k + 1);
+        for node in 0..self.ids.len() {
+            closest.push(Candidate { distance: self.distance(query, self.vector(node)), node });
+            if closest.len() > k {
+                closest.pop();
+            }
+        }
+        closest.into_sorted_vec()
+    }
+
+    fn hits(&self, found: Vec<Candidate>) -> Vec<SearchHit<'_, M>> {
+        found.into_iter()
+            .map(|candidate| SearchHit {
+                id: &self.ids[candidate.node],
+                score: self.score(candidate.distance),
+                metadata: &self.metadata[candidate.node]
+            })
+            .collect()
+    }
+}
+
+const MAGIC: &[u8; 8] = b"OAIVIDX\0";
+const VERSION: u32 = 1;
+
+///ids and metadata, stored next to vectors as `<index file>.json`
+#[derive(Serialize)]
+struct SidecarRef<'a, M> {
+    ids: &'a [String],
+    metadata: &'a [M]
+}
+
+#[derive(Deserialize)]
+struct Sidecar<M> {
+    ids: Vec<String>,
+    metadata: Vec<M>
+}
+
+impl<M: Serialize> VectorIndex<M> {
+
+    ///Writes vectors to `path` and ids with metadata to `path` with `.json` appended.
+    ///Vector file starts with header: magic bytes, format version, metric, normalization flag,
+    ///dimensions, number of vectors and model name, followed by little-endian f32 values,
+    ///aligned to 4 bytes. Search graph is not saved, it is rebuilt by `hnsw` after loading.
+    ///Existing files are replaced, not overwritten, so index can be saved to the path it was opened from
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let model = self.model.as_deref().unwrap_or_default().as_bytes();
+        let sidecar = SidecarRef { ids: &self.ids, metadata: &self.metadata };
+        let json = serde_json::to_vec(&sidecar).map_err(OpenAiError::Serialization)?;
+        //files are written aside and renamed over targets, so index mapped from the same path
+        //keeps reading its old file instead of one that is being truncated
+        let vectors = write_temp(path, |file| {
+            file.write_all(MAGIC)?;
+            file.write_all(&VERSION.to_le_bytes())?;
+            file.write_all(&[self.metric as u8, self.normalize as u8, 0, 0])?;
+            file.write_all(&(self.dimensions as u32).to_le_bytes())?;
+            file.write_all(&(self.ids.len() as u64).to_le_bytes())?;
+            file.write_all(&(model.len() as u32).to_le_bytes())?;
+            file.write_all(model)?;
+            file.write_all(&[0; 3][..padding(model.len())])?;
+            for value in self.vectors.as_slice() {
+                file.write_all(&value.to_le_bytes())?;
+            }
+            Ok(())
+        })?;
+        let sidecar_path = sidecar_path(path);
+        let metadata = match write_temp(&sidecar_path, |file| file.write_all(&json)) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                let _ = std::fs::remove_file(&vectors);
+                return Err(err.into())
+            }
+        };
+        std::fs::rename(vectors, path)?;
+        std::fs::rename(metadata, sidecar_path)?;
+        Ok(())
+    }
+}
+
+impl<M: DeserializeOwned> VectorIndex<M> {
+
+    ///Opens index saved with `save`, checking that it was built with model of `request`,
+    ///and has dimensions requested by it (or default dimensions of known models).
+    ///Fails with `OpenAiError::ModelMismatch` or `OpenAiError::DimensionMismatch` otherwise
+    pub fn load(path: &Path, request: &EmbeddingRequest) -> Result<Self> {
+        let index = VectorIndex::open(path)?;
+        if index.model.as_deref() != Some(request.model.as_str()) {
+            return Err(OpenAiError::ModelMismatch {
+                expected: request.model.clone(),
+                actual: index.model.unwrap_or_default()
+            })
+        }
+        let dimensions = request.dimensions.map(|dimensions| dimensions as usize)
+            .or_else(|| default_dimensions(&request.model));
+        if let Some(expected) = dimensions {
+            if expected != index.dimensions {
+                return Err(OpenAiError::DimensionMismatch { expected, actual: index.dimensions })
+            }
+        }
+        Ok(index)
+    }
+
+    ///Opens index saved with `save` without checking its model. Vectors are memory-mapped,
+    ///not read, and copied only when index is changed. File must not be modified while index is open
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        //SAFETY: file is only read, and must not be changed by other processes while mapped, as documented
+        let map = unsafe { Mmap::map(&file)? };
+        let header = Header::parse(&map)?;
+        let sidecar: Sidecar<M> = serde_json::from_slice(&std::fs::read(sidecar_path(path))?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if sidecar.ids.len() != header.count || sidecar.metadata.len() != header.count {
+            return Err(invalid_data("number of ids or metadata does not match number of vectors").into())
+        }
+        let len = header.count * header.dimensions;
+        let aligned = map[header.offset..].as_ptr().align_offset(std::mem::align_of::<f32>()) == 0;
+        let vectors = if cfg!(target_endian = "little") && aligned {
+            Vectors::Mapped { map: Arc::new(map), offset: header.offset, len }
+        } else {
+            Vectors::Owned(map[header.offset..header.offset + len * 4].chunks_exact(4)
+                .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+                .collect())
+        };
+        Ok(VectorIndex {
+            model: header.model,
+            dimensions: header.dimensions,
+            metric: header.metric,
+            normalize: header.normalize,
+            positions: sidecar.ids.iter().enumerate().map(|(position, id)| (id.clone(), position)).collect(),
+            ids: sidecar.ids,
+            vectors,
+            metadata: sidecar.metadata,
+            hnsw: None
+        })
+    }
+}
+
+///header of saved index
+struct Header {
+    model: Option<String>,
+    metric: Metric,
+    normalize: bool,
+    dimensions: usize,
+    count: usize,
+    ///start of vectors in the file
+    offset: usize
+}
+
+impl Header {
+
+    fn parse(bytes: &[u8]) -> io::Result<Header> {
+        const FIXED: usize = 32;
+        if bytes.len() < FIXED || &bytes[..8] != MAGIC {
+            return Err(invalid_data("not a vector index file"))
+        }
+        let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        if u32_at(8) != VERSION {
+            return Err(invalid_data("unsupported vector index version"))
+        }
+        let metric = match bytes[12] {
+            0 => Metric::Cosine,
+            1 => Metric::Dot,
+            2 => Metric::Euclidean,
+            _ => return Err(invalid_data("unknown metric"))
+        };
+        let dimensions = u32_at(16) as usize;
+        let mut count = [0; 8];
+        count.copy_from_slice(&bytes[20..28]);
+        let count = u64::from_le_bytes(count) as usize;
+        let model_len = u32_at(28) as usize;
+        let offset = FIXED + model_len + padding(model_len);
+        let size = count.checked_mul(dimensions)
+            .and_then(|len| len.checked_mul(4))
+            .and_then(|size| size.checked_add(offset));
+        if size.is_none_or(|size| size > bytes.len()) {
+            return Err(invalid_data("vector index file is truncated"))
+        }
+        let model = std::str::from_utf8(&bytes[FIXED..FIXED + model_len])
+            .map_err(|_| invalid_data("model name is not valid UTF-8"))?;
+        Ok(Header {
+            model: (!model.is_empty()).then(|| model.to_string()),
+            metric,
+            normalize: bytes[13] != 0,
+            dimensions,
+            count,
+            offset
+        })
+    }
+}
+
+///vectors of the index: owned, or mapped from index file until index is changed
+#[derive(Clone, Debug)]
+enum Vectors {
+    Owned(Vec<f32>),
+    Mapped { map: Arc<Mmap>, offset: usize, len: usize }
+}
+
+impl Vectors {
+
+    fn as_slice(&self) -> &[f32] {
+        match self {
+            Vectors::Owned(vectors) => vectors,
+            Vectors::Mapped { map, offset, len } => {
+                let bytes = &map[*offset..*offset + len * 4];
+                //SAFETY: mapping is only created on little-endian hosts, with offset aligned for f32
+                //and bounds checked by header, and any bit pattern is a valid f32
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, *len) }
+            }
+        }
+    }
+
+    ///copies mapped vectors into memory before first change
+    fn to_mut(&mut self) -> &mut Vec<f32> {
+        if let Vectors::Mapped { .. } = self {
+            *self = Vectors::Owned(self.as_slice().to_vec());
+        }
+        match self {
+            Vectors::Owned(vectors) => vectors,
+            Vectors::Mapped { .. } => unreachable!("mapped vectors were just copied")
+        }
+    }
+}
+
+///default dimensions of OpenAI embedding models
+fn default_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-3-large" => Some(3072),
+        "text-embedding-3-small" | "text-embedding-ada-002" => Some(1536),
+        _ => None
+    }
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = OsString::from(path.as_os_str());
+    sidecar.push(".json");
+    PathBuf::from(sidecar)
+}
+
+///writes file next to `path` under temporary name, and returns that name.
+///File is removed, if writing fails
+fn write_temp(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> io::Result<PathBuf> {
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(format!(".{}.tmp", rand::random::<u32>()));
+    let temp = PathBuf::from(temp);
+    let written = File::create(&temp).and_then(|file| {
+        let mut file = BufWriter::new(file);
+        write(&mut file)?;
+        file.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()
+    });
+    match written {
+        Ok(()) => Ok(temp),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+///zero bytes after model name, that align vectors to 4 bytes
+fn padding(model_len: usize) -> usize {
+    (4 - model_len % 4) % 4
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot(a, b) / norms
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = dot(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= norm);
+    }
+}
+
+///node with its distance to the query, ordered by distance
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    distance: f32,
+    node: usize
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+///Hierarchical navigable small world graph. Every node is on layer 0 and, with decreasing
+///probability, on layers above it. Search descends from the sparse top layer to layer 0,
+///moving to neighbors closer to the query.
+#[derive(Clone, Debug)]
+struct Hnsw {
+    params: HnswParams,
+    ///neighbors of every node, for each layer it is on
+    links: Vec<Vec<Vec<usize>>>,
+    entry: Option<usize>,
+    ///state of random generator for node layers, fixed so same inserts give same graph
+    seed: u64
+}
+
+impl Hnsw {
+
+    fn new(params: HnswParams) -> Self {
+        Hnsw { params, links: Vec::new(), entry: None, seed: 0x9E37_79B9_7F4A_7C15 }
+    }
+
+    ///top layer of the new node, drawn from exponential distribution
+    fn random_level(&mut self) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let uniform = (self.seed >> 11) as f64 / (1u64 << 53) as f64;
+        let multiplier = 1.0 / (self.params.m as f64).ln();
+        (-(1.0 - uniform).ln() * multiplier).floor() as usize
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.params.m * 2 } else { self.params.m }
+    }
+
+    ///links new node into graph, or relinks node, which vector was replaced
+    fn insert(&mut self, node: usize, distance: impl Fn(usize, usize) -> f32) {
+        let mut start = self.entry;
+        if node == self.links.len() {
+            let level = self.random_level();
+            self.links.push(vec![Vec::new(); level + 1]);
+        } else {
+            let former = self.unlink(node, &distance);
+            if start == Some(node) {
+                //node stays the entry, but search for its new neighbors starts
+                //from its former neighbor on the highest layer
+                start = former.iter().rev().find_map(|neighbors| neighbors.first().copied());
+            }
+        }
+        let level = self.links[node].len() - 1;
+        let entry = match start {
+            Some(entry) => entry,
+            None => {
+                self.entry = Some(node);
+                return
+            }
+        };
+        let top = self.links[entry].len() - 1;
+        let to_node = |other: usize| distance(node, other);
+        let mut entries = vec![entry];
+        for layer in (level + 1..=top).rev() {
+            entries = nodes(self.search_layer(&to_node, &entries, 1, layer));
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&to_node, &entries, self.params.ef_construction, layer);
+            let neighbors = found.iter()
+                .map(|candidate| candidate.node)
+                .filter(|&neighbor| neighbor != node)
+                .take(self.params.m)
+                .collect::<Vec<_>>();
+            for neighbor in neighbors {
+                self.link(node, neighbor, layer, &distance);
+                self.link(neighbor, node, layer, &distance);
+            }
+            entries = nodes(found);
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    ///Removes links of the node, whose vector was replaced, and links to it from its neighbors.
+    ///Each former neighbor is linked to the closest of the other ones instead, so it stays reachable.
+    ///Returns former neighbors on every layer of the node
+    fn unlink(&mut self, node: usize, distance: &impl Fn(usize, usize) -> f32) -> Vec<Vec<usize>> {
+        let former = self.links[node].iter_mut().map(std::mem::take).collect::<Vec<_>>();
+        for (layer, neighbors) in former.iter().enumerate() {
+            for &neighbor in neighbors {
+                self.links[neighbor][layer].retain(|&linked| linked != node);
+            }
+            for &neighbor in neighbors {
+                let links = &self.links[neighbor][layer];
+                let closest = neighbors.iter()
+                    .copied()
+                    .filter(|&other| other != neighbor && !links.contains(&other))
+                    .min_by(|a, b| distance(neighbor, *a).total_cmp(&distance(neighbor, *b)));
+                if let Some(closest) = closest {
+                    self.link(neighbor, closest, layer, distance);
+                }
+            }
+        }
+        former
+    }
+
+    ///adds link, dropping the farthest one if node has too many
+    fn link(&mut self, from: usize, to: usize, layer: usize, distance: &impl Fn(usize, usize) -> f32) {
+        let max = self.max_links(layer);
+        let links = &mut self.links[from][layer];
+        if links.contains(&to) {
+            return
+        }
+        links.push(to);
+        if links.len() > max {
+            links.sort_by(|a, b| distance(from, *a).total_cmp(&distance(from, *b)));
+            links.truncate(max);
+        }
+    }
+
+    fn search(&self, distance: impl Fn(usize) -> f32, k: usize) -> Vec<Candidate> {
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => return Vec::new()
+        };
+        let mut entries = vec![entry];
+        for layer in (1..self.links[entry].len()).rev() {
+            entries = nodes(self.search_layer(&distance, &entries, 1, layer));
+        }
+        let mut found = self.search_layer(&distance, &entries, self.params.ef_search.max(k), 0);
+        found.truncate(k);
+        found
+    }
+
+    ///up to `ef` nodes of the layer closest to the query, sorted from the closest
+    fn search_layer(&self, distance: &impl Fn(usize) -> f32, entries: &[usize], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited = entries.iter().copied().collect::<HashSet<_>>();
+        let mut candidates = BinaryHeap::new();
+        let mut found = BinaryHeap::new();
+        for &node in entries {
+            let candidate = Candidate { distance: distance(node), node };
+            candidates.push(Reverse(candidate));
+            found.push(candidate);
+        }
+        while let Some(Reverse(current)) = candidates.pop() {
+            let farthest = found.peek().map_or(f32::INFINITY, |farthest: &Candidate| farthest.distance);
+            if found.len() >= ef && current.distance > farthest {
+                break
+            }
+            for &neighbor in self.links[current.node].get(layer).into_iter().flatten() {
+                if !visited.insert(neighbor) {
+                    continue
+                }
+                let candidate = Candidate { distance: distance(neighbor), node: neighbor };
+                let farthest = found.peek().map_or(f32::INFINITY, |farthest: &Candidate| farthest.distance);
+                if found.len() < ef || candidate.distance < farthest {
+                    candidates.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+}
+
+fn nodes(candidates: Vec<Candidate>) -> Vec<usize> {
+    candidates.into_iter().map(|candidate| candidate.node).collect()
+}
@@ -1,6 +1,15 @@
+pub mod batch;
+pub mod index;
+pub mod splitter;
 
-use crate::{Input, JsonRequest};
-use serde::{Serialize,Deserialize};
+use std::fmt::Formatter;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crate::{Input, JsonRequest, OpenAiError, Result};
+#[cfg(not(feature = "tokenizer"))]
+use crate::limiter::estimate_tokens;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::{Error, SeqAccess, Visitor};
 
 ///embedding request as defiled by https://platform.openai.com/docs/api-reference/embeddings
 /// # Usage example
@@ -17,11 +26,34 @@ pub struct EmbeddingRequest{
     pub model:String,
     pub input:Input,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub user: Option<String>
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EncodingFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u32>
+}
+
+///Format embeddings are sent in. Both are decoded into `Vec<f32>`,
+///but base64 is smaller and decoded without parsing float text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    Float,
+    Base64
 }
 
 impl JsonRequest<EmbeddingResponse> for EmbeddingRequest{
     const ENDPOINT: &'static str = "/embeddings";
+
+    fn validate(&self) -> Result<()> {
+        if self.dimensions.is_some() && !self.model.starts_with("text-embedding-3") {
+            return Err(OpenAiError::InvalidParameter {
+                param: "dimensions".to_string(),
+                reason: format!("not supported by {}", self.model)
+            })
+        }
+        Ok(())
+    }
 }
 
 impl EmbeddingRequest {
@@ -30,6 +62,8 @@ impl EmbeddingRequest {
             model: "text-embedding-ada-002".to_string(),
             input,
             user: None,
+            encoding_format: None,
+            dimensions: None,
         }
     }
 
@@ -39,6 +73,8 @@ impl EmbeddingRequest {
             model,
             input,
             user: None,
+            encoding_format: None,
+            dimensions: None,
         }
     }
 
@@ -46,6 +82,17 @@ impl EmbeddingRequest {
         self.user = Some(user);
         self
     }
+
+    pub fn encoding_format(mut self, encoding_format: EncodingFormat) -> Self {
+        self.encoding_format = Some(encoding_format);
+        self
+    }
+
+    ///shortens embeddings to given number of dimensions, only supported by text-embedding-3 models
+    pub fn dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
 }
 
 
@@ -58,10 +105,45 @@ pub struct Usage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Struct {
     pub object: String,
-    pub embedding: Vec<f64>,
+    ///read from either float array or base64 string, depending on `encoding_format`
+    #[serde(deserialize_with = "embedding_values")]
+    pub embedding: Vec<f32>,
     pub index: i64,
 }
 
+///reads float array, or base64 string of little-endian f32 values
+fn embedding_values<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<f32>, D::Error> {
+    struct EmbeddingVisitor;
+
+    impl<'de> Visitor<'de> for EmbeddingVisitor {
+        type Value = Vec<f32>;
+
+        fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+            formatter.write_str("array of floats or base64 string")
+        }
+
+        fn visit_str<E: Error>(self, value: &str) -> std::result::Result<Vec<f32>, E> {
+            let bytes = STANDARD.decode(value).map_err(E::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(E::custom("base64 embedding is not a whole number of f32 values"))
+            }
+            Ok(bytes.chunks_exact(4)
+                .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+                .collect())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Vec<f32>, A::Error> {
+            let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(value) = seq.next_element()? {
+                values.push(value);
+            }
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_any(EmbeddingVisitor)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EmbeddingResponse {
     pub object: String,
@@ -69,3 +151,15 @@ pub struct EmbeddingResponse {
     pub model: String,
     pub usage: Usage,
 }
+
+///tokens of the text with model's tokenizer if `tokenizer` feature is enabled, estimate otherwise
+#[cfg(feature = "tokenizer")]
+pub(crate) fn count_tokens(model: &str, text: &str) -> u64 {
+    crate::tokenizer::Tokenizer::for_model(model).count(text) as u64
+}
+
+///tokens of the text with model's tokenizer if `tokenizer` feature is enabled, estimate otherwise
+#[cfg(not(feature = "tokenizer"))]
+pub(crate) fn count_tokens(_model: &str, text: &str) -> u64 {
+    estimate_tokens(&serde_json::json!({ "input": text }))
+}
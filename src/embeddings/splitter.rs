@@ -0,0 +1,275 @@
+use std::ops::Range;
+use crate::embeddings::count_tokens;
+
+///piece of the source text, small enough to be embedded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    ///byte range of the chunk in the source text
+    pub range: Range<usize>,
+    pub tokens: usize,
+    ///titles of Markdown sections the chunk is in, from the top level
+    pub headings: Vec<String>
+}
+
+///Splits documents into chunks of limited number of tokens, for embedding.
+///Chunks never cross Markdown headings, end at paragraph ends when that keeps them at least half full,
+///and otherwise at sentence ends. Sentences longer than a chunk are split at words,
+///and words (or text without spaces) longer than a chunk are halved until they fit.
+///Consecutive chunks of the same section share up to `overlap` tokens of whole sentences.
+///
+///Tokens are counted with the model's tokenizer if `tokenizer` feature is enabled,
+///otherwise they are estimated with `limiter::estimate_tokens`.
+/// # Usage example
+/// ```
+/// use openai_req::embeddings::splitter::TextSplitter;
+///
+/// let document = "# Cats\n\nCats sleep a lot. They also purr.\n\n# Dogs\n\nDogs bark.";
+/// let chunks = TextSplitter::new("text-embedding-3-small").chunk_tokens(256).split(document);
+/// assert_eq!(chunks[1].headings, vec!["Dogs"]);
+/// assert_eq!(&document[chunks[1].range.clone()], chunks[1].text);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TextSplitter {
+    model: String,
+    chunk_tokens: usize,
+    overlap: usize
+}
+
+///sentence or word of the section, with whitespace that follows it
+#[derive(Clone, Debug)]
+struct Unit {
+    range: Range<usize>,
+    tokens: usize,
+    paragraph_end: bool
+}
+
+///part of the text under single Markdown heading, without the heading line
+struct Section {
+    range: Range<usize>,
+    headings: Vec<String>
+}
+
+impl TextSplitter {
+
+    ///splitter with 512 token chunks and 64 token overlap
+    pub fn new(model: &str) -> Self {
+        TextSplitter { model: model.to_string(), chunk_tokens: 512, overlap: 64 }
+    }
+
+    pub fn chunk_tokens(mut self, chunk_tokens: usize) -> Self {
+        self.chunk_tokens = chunk_tokens.max(1);
+        self
+    }
+
+    ///tokens repeated from the end of the previous chunk, kept below half of chunk size
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn split(&self, text: &str) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        for section in sections(text) {
+            let units = self.units(text, section.range);
+            self.pack(text, &units, &section.headings, &mut chunks);
+        }
+        chunks
+    }
+
+    fn count(&self, text: &str) -> usize {
+        count_tokens(&self.model, text) as usize
+    }
+
+    ///sentences of the section, sentences that do not fit into a chunk are split further
+    fn units(&self, text: &str, section: Range<usize>) -> Vec<Unit> {
+        let mut units = Vec::new();
+        for paragraph in paragraphs(text, section) {
+            let sentences = split_after(text, paragraph, |current, next| {
+                current == '\n' || matches!(current, '。' | '！' | '？')
+                    || (matches!(current, '.' | '!' | '?') && next.is_none_or(char::is_whitespace))
+            });
+            let last = sentences.len().saturating_sub(1);
+            for (i, sentence) in sentences.into_iter().enumerate() {
+                let mut pieces = self.fit(text, sentence);
+                if let Some(piece) = pieces.last_mut() {
+                    piece.paragraph_end = i == last;
+                }
+                units.extend(pieces);
+            }
+        }
+        units
+    }
+
+    ///splits range into words, or into parts of words, until every piece fits into a chunk.
+    ///Only single character can be left over the limit
+    fn fit(&self, text: &str, range: Range<usize>) -> Vec<Unit> {
+        let tokens = self.count(&text[range.clone()]);
+        if tokens <= self.chunk_tokens {
+            return vec![Unit { range, tokens, paragraph_end: false }]
+        }
+        let words = split_after(text, range.clone(), |current, next| {
+            !current.is_whitespace() && next.is_some_and(char::is_whitespace)
+        });
+        if words.len() > 1 {
+            return words.into_iter().flat_map(|word| self.fit(text, word)).collect()
+        }
+        //single word, like long url, encoded data or text without spaces, is halved until pieces fit
+        let mut middle = range.start + range.len() / 2;
+        while !text.is_char_boundary(middle) {
+            middle += 1;
+        }
+        if middle == range.start || middle == range.end {
+            return vec![Unit { range, tokens, paragraph_end: false }]
+        }
+        let mut pieces = self.fit(text, range.start..middle);
+        pieces.extend(self.fit(text, middle..range.end));
+        pieces
+    }
+
+    fn pack(&self, text: &str, units: &[Unit], headings: &[String], chunks: &mut Vec<Chunk>) {
+        let overlap = self.overlap.min(self.chunk_tokens / 2);
+        let mut start = 0;
+        while start < units.len() {
+            let mut end = start;
+            let mut tokens = 0;
+            while end < units.len() && (end == start || tokens + units[end].tokens <= self.chunk_tokens) {
+                tokens += units[end].tokens;
+                end += 1;
+            }
+            if end < units.len() {
+                //last paragraph end, that keeps chunk at least half full
+                let mut sum = 0;
+                for (i, unit) in units.iter().enumerate().take(end).skip(start) {
+                    sum += unit.tokens;
+                    if unit.paragraph_end && sum * 2 >= self.chunk_tokens {
+                        end = i + 1;
+                    }
+                }
+            }
+            //tokens of joined units can differ from their sum, so chunk is counted again and shrunk if needed
+            let chunk = loop {
+                match self.chunk(text, units[start].range.start..units[end - 1].range.end, headings) {
+                    Some(chunk) if chunk.tokens > self.chunk_tokens && end > start + 1 => end -= 1,
+                    chunk => break chunk
+                }
+            };
+            chunks.extend(chunk);
+            if end == units.len() {
+                break
+            }
+            let mut next = end;
+            let mut repeated = 0;
+            while next > start + 1 && repeated + units[next - 1].tokens <= overlap {
+                repeated += units[next - 1].tokens;
+                next -= 1;
+            }
+            start = next;
+        }
+    }
+
+    ///chunk of the range without surrounding whitespace, `None` if there is nothing else
+    fn chunk(&self, text: &str, range: Range<usize>, headings: &[String]) -> Option<Chunk> {
+        let raw = &text[range.clone()];
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return None
+        }
+        let start = range.start + raw.len() - raw.trim_start().len();
+        Some(Chunk {
+            text: trimmed.to_string(),
+            range: start..start + trimmed.len(),
+            tokens: self.count(trimmed),
+            headings: headings.to_vec()
+        })
+    }
+}
+
+///splits text at Markdown headings, headings in fenced code blocks are ignored.
+///Heading lines are not part of any section, their titles are carried in `headings` instead
+fn sections(text: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_code = false;
+    let titles = |headings: &[(usize, String)]| headings.iter().map(|(_, title)| title.clone()).collect();
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+        } else if let Some((level, title)) = heading(line).filter(|_| !in_code) {
+            if offset > start {
+                sections.push(Section { range: start..offset, headings: titles(&headings) });
+            }
+            headings.retain(|(parent, _)| *parent < level);
+            headings.push((level, title));
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    if text.len() > start {
+        sections.push(Section { range: start..text.len(), headings: titles(&headings) });
+    }
+    sections
+}
+
+///level and title of ATX heading line, like `## Installation`
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with([' ', '\t', '\n', '\r'])) {
+        return None
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+///ranges of paragraphs of the section, each with blank lines that follow it
+fn paragraphs(text: &str, section: Range<usize>) -> Vec<Range<usize>> {
+    let mut paragraphs = Vec::new();
+    let mut start = section.start;
+    let mut offset = section.start;
+    let mut has_text = false;
+    let mut previous_blank = false;
+    for line in text[section.clone()].split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !blank && previous_blank && has_text {
+            paragraphs.push(start..offset);
+            start = offset;
+        }
+        has_text |= !blank;
+        previous_blank = blank;
+        offset += line.len();
+    }
+    if section.end > start {
+        paragraphs.push(start..section.end);
+    }
+    paragraphs
+}
+
+///splits range after characters, for which `boundary` returns true given the character and the next one.
+///Whitespace after boundary is kept in the piece before it
+fn split_after(text: &str, range: Range<usize>, boundary: impl Fn(char, Option<char>) -> bool) -> Vec<Range<usize>> {
+    let chars = text[range.clone()].char_indices()
+        .map(|(position, c)| (range.start + position, c))
+        .collect::<Vec<_>>();
+    let mut pieces = Vec::new();
+    let mut start = range.start;
+    let mut i = 0;
+    while i < chars.len() {
+        let current = chars[i].1;
+        i += 1;
+        if boundary(current, chars.get(i).map(|(_, c)| *c)) {
+            while i < chars.len() && chars[i].1.is_whitespace() {
+                i += 1;
+            }
+            let end = chars.get(i).map_or(range.end, |(position, _)| *position);
+            pieces.push(start..end);
+            start = end;
+        }
+    }
+    if start < range.end {
+        pieces.push(start..range.end);
+    }
+    pieces
+}
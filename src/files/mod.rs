@@ -195,7 +195,7 @@ impl FileUploadRequest {
 impl AsyncTryFrom<FileUploadRequest> for Form{
     type Error = io::Error;
 
-    async fn try_from(value: FileUploadRequest) -> anyhow::Result<Self, Self::Error> {
+    async fn try_from(value: FileUploadRequest) -> Result<Self, Self::Error> {
         let form =
             Form::new()
                 .part("purpose",Part::text(value.purpose))
@@ -281,12 +281,12 @@ pub enum ImageSize {
     S1024,
 }
 
-impl ToString for ImageSize {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            ImageSize::S256 => String::from("256x256"),
-            ImageSize::S512 => String::from("512x512"),
-            ImageSize::S1024 => String::from("1024x1024"),
+            ImageSize::S256 => write!(f,"256x256"),
+            ImageSize::S512 => write!(f,"512x512"),
+            ImageSize::S1024 => write!(f,"1024x1024"),
         }
     }
 }
@@ -8,17 +8,28 @@ pub mod fine_tunes;
 pub mod moderations;
 pub mod audio;
 pub mod model;
+pub mod retry;
+pub mod meta;
+pub mod limiter;
+pub mod azure;
+pub mod auth;
+#[cfg(feature = "tokenizer")]
+pub mod tokenizer;
+pub mod logit_bias;
+pub mod logprobs;
+pub mod sse;
 mod conversions;
+mod trace;
 
-use anyhow::Result;
 use std::io;
 use std::path::PathBuf;
 use std::pin::Pin;
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::TryFutureExt;
-use reqwest::{Body, Client, multipart, RequestBuilder, Response};
+use reqwest::{Body, Client, multipart, RequestBuilder, Response, StatusCode};
 use reqwest::multipart::Part;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::de::DeserializeOwned;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
@@ -29,6 +40,13 @@ use with_id::WithRefId;
 use std::fmt::{Debug, Display, Formatter};
 use serde::{Serialize, Deserialize};
 use crate::conversions::AsyncTryInto;
+use crate::retry::RetryPolicy;
+use crate::meta::{ResponseMeta, WithMeta};
+use crate::limiter::{RateLimiter, Reservation};
+use crate::trace::RequestSpan;
+use crate::azure::AzureConfig;
+use crate::auth::{AuthProvider, Credential, StaticKey};
+use std::sync::Arc;
 
 
 /// This is main client structure required for all requests.
@@ -43,8 +61,12 @@ use crate::conversions::AsyncTryInto;
 #[derive(Debug, Clone)]
 pub struct OpenAiClient {
     url:String,
-    key:String,
-    client:Client
+    auth:Arc<dyn AuthProvider>,
+    client:Client,
+    headers:HeaderMap,
+    retry:RetryPolicy,
+    limiter:Option<Arc<RateLimiter>>,
+    azure:Option<AzureConfig>
 }
 
 impl OpenAiClient {
@@ -79,32 +101,342 @@ impl OpenAiClient {
     pub fn with_url_and_client(key: &str, url: &str, client: &Client)->Self{
         OpenAiClient {
             url: url.to_string(),
-            key: key.to_string(),
-            client: client.clone()
+            auth: Arc::new(StaticKey::new(key)),
+            client: client.clone(),
+            headers: HeaderMap::new(),
+            retry: RetryPolicy::none(),
+            limiter: None,
+            azure: None
         }
     }
+
+    /// client for Azure OpenAI resource, like `https://my-resource.openai.azure.com`.
+    /// Requests are routed to deployments configured in `azure`,
+    /// and key is sent in `api-key` header
+    pub fn azure(key: &str, resource_url: &str, azure: AzureConfig) -> Self {
+        let mut client = OpenAiClient::with_url(key, resource_url);
+        client.azure = Some(azure);
+        client
+    }
+
+    /// client with default url, that takes credentials from `auth` before every request,
+    /// instead of using fixed key
+    pub fn with_auth(auth: impl AuthProvider + 'static) -> Self {
+        OpenAiClient::new("").auth(auth)
+    }
+
+    /// replaces key given in constructor with credentials provider
+    pub fn auth(mut self, auth: impl AuthProvider + 'static) -> Self {
+        self.auth = Arc::new(auth);
+        self
+    }
+
+    /// builder for clients that need more than key and url,
+    /// like organization and project headers
+    pub fn builder(key: &str) -> OpenAiClientBuilder {
+        OpenAiClientBuilder::new(key)
+    }
+
+    /// by default every request is sent exactly once,
+    /// set retry policy to repeat requests that failed with 429, 5xx or connection errors
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// makes requests wait in queue, instead of exceeding requests-per-minute
+    /// and tokens-per-minute budgets. Limiter is shared by all clones of this client
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    ///full url of the request to `endpoint`, with `path` (like object id) appended.
+    ///`model` is only asked for in Azure mode, where it selects deployment
+    pub(crate) fn url_for(&self, endpoint: &str, path: &str, model: impl FnOnce() -> Option<String>) -> String {
+        match &self.azure {
+            Some(azure) => azure.url(&self.url, endpoint, path, model().as_deref()),
+            None => self.url.to_owned() + endpoint + path
+        }
+    }
+
+    ///asks auth provider for credential and adds it to the request,
+    ///Azure expects key in `api-key` header instead of bearer auth
+    pub(crate) async fn authorize(&self, request: RequestBuilder) -> Result<(RequestBuilder, Credential)> {
+        let credential = self.auth.credential().await?;
+        let request = credential.apply(request, self.azure.is_some())?;
+        Ok((request, credential))
+    }
+}
+
+/// Builds `OpenAiClient` with organization, project and any other headers,
+/// that will be sent with every request.
+/// # Usage example
+/// ```
+/// use openai_req::OpenAiClient;
+///
+/// let client = OpenAiClient::builder("{YOUR_API_KEY}")
+///     .organization("org-123")
+///     .project("proj_123")
+///     .header("X-Team", "search")
+///     .build()?;
+/// # Ok::<(), openai_req::OpenAiError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenAiClientBuilder {
+    auth:Arc<dyn AuthProvider>,
+    url:String,
+    client:Option<Client>,
+    headers:Vec<(String,String)>,
+    retry:RetryPolicy,
+    limiter:Option<Arc<RateLimiter>>,
+    azure:Option<AzureConfig>
+}
+
+impl OpenAiClientBuilder {
+
+    pub fn new(key: &str) -> Self {
+        OpenAiClientBuilder {
+            auth: Arc::new(StaticKey::new(key)),
+            url: OpenAiClient::URL.to_string(),
+            client: None,
+            headers: Vec::new(),
+            retry: RetryPolicy::none(),
+            limiter: None,
+            azure: None
+        }
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    pub fn client(mut self, client: &Client) -> Self {
+        self.client = Some(client.clone());
+        self
+    }
+
+    ///sets `OpenAI-Organization` header
+    pub fn organization(self, organization: &str) -> Self {
+        self.header("OpenAI-Organization", organization)
+    }
+
+    ///sets `OpenAI-Project` header
+    pub fn project(self, project: &str) -> Self {
+        self.header("OpenAI-Project", project)
+    }
+
+    ///adds arbitrary header, sent with every request
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    ///replaces key with credentials provider, see `OpenAiClient::auth`
+    pub fn auth(mut self, auth: impl AuthProvider + 'static) -> Self {
+        self.auth = Arc::new(auth);
+        self
+    }
+
+    ///switches client to Azure OpenAI mode, `url` should be set to the resource url
+    pub fn azure(mut self, azure: AzureConfig) -> Self {
+        self.azure = Some(azure);
+        self
+    }
+
+    ///fails with `OpenAiError::InvalidHeader` if any header name or value can not be sent
+    pub fn build(self) -> Result<OpenAiClient> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| OpenAiError::InvalidHeader(name.clone()))?;
+            let header_value = HeaderValue::from_str(&value)
+                .map_err(|_| OpenAiError::InvalidHeader(name))?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(OpenAiClient {
+            url: self.url,
+            auth: self.auth,
+            client: self.client.unwrap_or_default(),
+            headers,
+            retry: self.retry,
+            limiter: self.limiter,
+            azure: self.azure
+        })
+    }
 }
 
-///common error type used by api client traits, wraps underlying reqwest::Error,
-///but also tries to provide response body, so error is easier to debug
+///result type returned by all api client traits
+pub type Result<T, E = OpenAiError> = std::result::Result<T, E>;
+
+///common error type used by api client traits.
+///Errors reported by API are split by status code, so they can be matched on,
+///and carry both status code and error details sent by API.
+///New variants can be added in minor versions, so matches on it need a wildcard arm.
 #[derive(Debug)]
-pub struct Error{
-    pub(crate) response:ApiError,
-    pub(crate) inner:reqwest::Error
+#[non_exhaustive]
+pub enum OpenAiError {
+    ///401 and 403 responses, key is missing, invalid or has no access to resource
+    Auth{ status: StatusCode, details: ApiErrorDetails },
+    ///429 responses, either rate limit or quota is exceeded
+    RateLimit{ status: StatusCode, details: ApiErrorDetails },
+    ///all other 4xx responses, request is malformed or refers to non-existing resource
+    InvalidRequest{ status: StatusCode, details: ApiErrorDetails },
+    ///5xx responses, something went wrong on API side
+    Server{ status: StatusCode, details: ApiErrorDetails },
+    ///any other unsuccessful status
+    Http{ status: StatusCode, details: ApiErrorDetails },
+    ///error sent by API in the middle of event stream, after successful status was already received
+    Stream{ details: ApiErrorDetails },
+    ///successful response body could not be parsed into expected type, raw body is preserved
+    Deserialization{ body: String, source: serde_json::Error },
+    ///request could not be serialized
+    Serialization(serde_json::Error),
+    ///header with given name has invalid name or value, and can not be sent
+    InvalidHeader(String),
+    ///auth provider could not supply credential
+    Credential(String),
+    ///model kept calling tools after given number of requests
+    ToolIterations(usize),
+    ///model refused to give structured answer, holds its explanation
+    Refusal(String),
+    ///answer was cut by token limit before JSON was complete, holds partial content
+    Truncated(String),
+    ///successful response did not contain any choices to take answer from
+    NoChoices,
+    ///request parameter is not supported by the model, or has invalid value.
+    ///Detected before request is sent
+    InvalidParameter{ param: String, reason: String },
+    ///vector has different number of dimensions than index it is used with
+    DimensionMismatch{ expected: usize, actual: usize },
+    ///saved index was built with different embedding model
+    ModelMismatch{ expected: String, actual: String },
+    ///local file access failed
+    Io(io::Error),
+    ///request could not be sent or response could not be received
+    Transport(reqwest::Error)
+}
+
+impl OpenAiError {
+
+    ///status code of the response, if error was received from API
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            OpenAiError::Auth { status, .. } |
+            OpenAiError::RateLimit { status, .. } |
+            OpenAiError::InvalidRequest { status, .. } |
+            OpenAiError::Server { status, .. } |
+            OpenAiError::Http { status, .. } => Some(*status),
+            OpenAiError::Transport(err) => err.status(),
+            _ => None
+        }
+    }
+
+    ///error details sent by API, if error was received from API
+    pub fn details(&self) -> Option<&ApiErrorDetails> {
+        match self {
+            OpenAiError::Auth { details, .. } |
+            OpenAiError::RateLimit { details, .. } |
+            OpenAiError::InvalidRequest { details, .. } |
+            OpenAiError::Server { details, .. } |
+            OpenAiError::Http { details, .. } |
+            OpenAiError::Stream { details } => Some(details),
+            _ => None
+        }
+    }
+
+    ///picks variant according to status code class
+    pub fn from_status(status: StatusCode, details: ApiErrorDetails) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => OpenAiError::Auth { status, details },
+            StatusCode::TOO_MANY_REQUESTS => OpenAiError::RateLimit { status, details },
+            status if status.is_client_error() => OpenAiError::InvalidRequest { status, details },
+            status if status.is_server_error() => OpenAiError::Server { status, details },
+            status => OpenAiError::Http { status, details }
+        }
+    }
+
+    ///reads body of unsuccessful response. If body is not an error structure
+    ///(for example, it was returned by proxy), it is kept as error message
+    pub(crate) async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(err) => return OpenAiError::Transport(err)
+        };
+        let details = match serde_json::from_str::<ApiError>(&body) {
+            Ok(api_error) => api_error.error,
+            Err(_) => ApiErrorDetails {
+                message: body,
+                kind: status.canonical_reason().unwrap_or("unknown").to_string(),
+                param: None,
+                code: None
+            }
+        };
+        OpenAiError::from_status(status, details)
+    }
 }
 
-impl Display for Error {
+impl Display for OpenAiError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f,"{}",self.response)
+        match self {
+            OpenAiError::Auth { status, details } |
+            OpenAiError::RateLimit { status, details } |
+            OpenAiError::InvalidRequest { status, details } |
+            OpenAiError::Server { status, details } |
+            OpenAiError::Http { status, details } => write!(f,"{}: {}",status,details),
+            OpenAiError::Stream { details } => write!(f,"error in event stream: {}",details),
+            OpenAiError::Deserialization { body, source } => write!(f,"{}, response body: {}",source,body),
+            OpenAiError::Serialization(err) => write!(f,"{}",err),
+            OpenAiError::InvalidHeader(name) => write!(f,"invalid header: {}",name),
+            OpenAiError::Credential(message) => write!(f,"credential unavailable: {}",message),
+            OpenAiError::ToolIterations(max) => write!(f,"model still calls tools after {} requests",max),
+            OpenAiError::Refusal(refusal) => write!(f,"model refused to answer: {}",refusal),
+            OpenAiError::Truncated(_) => write!(f,"answer was truncated by token limit"),
+            OpenAiError::NoChoices => write!(f,"response has no choices"),
+            OpenAiError::InvalidParameter { param, reason } => write!(f,"invalid parameter {}: {}",param,reason),
+            OpenAiError::DimensionMismatch { expected, actual } => write!(f,"vector has {} dimensions, expected {}",actual,expected),
+            OpenAiError::ModelMismatch { expected, actual } => write!(f,"index was built with {}, but {} is used",actual,expected),
+            OpenAiError::Io(err) => write!(f,"{}",err),
+            OpenAiError::Transport(err) => write!(f,"{}",err)
+        }
     }
 }
 
-impl std::error::Error for Error {
+impl std::error::Error for OpenAiError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.inner)
+        match self {
+            OpenAiError::Deserialization { source, .. } => Some(source),
+            OpenAiError::Serialization(err) => Some(err),
+            OpenAiError::Io(err) => Some(err),
+            OpenAiError::Transport(err) => Some(err),
+            _ => None
+        }
     }
 }
 
+impl From<reqwest::Error> for OpenAiError {
+    fn from(value: reqwest::Error) -> Self {
+        OpenAiError::Transport(value)
+    }
+}
+
+impl From<io::Error> for OpenAiError {
+    fn from(value: io::Error) -> Self {
+        OpenAiError::Io(value)
+    }
+}
 
 ///structure returned by OpenAI for errors
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -125,14 +457,20 @@ pub struct ApiErrorDetails {
 
 impl Display for ApiError{
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self.error.param {
-            None => match &self.error.code {
-                None => write!(f,"{}",self.error.message),
-                Some(code) => write!(f,"{}, code:{}",self.error.message,code)
+        write!(f,"{}",self.error)
+    }
+}
+
+impl Display for ApiErrorDetails{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.param {
+            None => match &self.code {
+                None => write!(f,"{}",self.message),
+                Some(code) => write!(f,"{}, code:{}",self.message,code)
             }
-            Some(param) => match &self.error.code {
-                None => write!(f,"{}, param:{}",self.error.message,param),
-                Some(code) => write!(f,"{}, param:{}, code: {}",self.error.message,param,code)
+            Some(param) => match &self.code {
+                None => write!(f,"{}, param:{}",self.message,param),
+                Some(code) => write!(f,"{}, param:{}, code: {}",self.message,param,code)
             }
         }
     }
@@ -174,11 +512,67 @@ pub struct DeleteResponse {
 }
 
 ///common struct that comes up in responses
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Usage{
     pub prompt_tokens: u64,
     pub completion_tokens: u64,
-    pub total_tokens: u64
+    pub total_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>
+}
+
+impl Usage {
+
+    ///prompt tokens served from prompt cache, billed at lower rate
+    pub fn cached_tokens(&self) -> u64 {
+        self.prompt_tokens_details.as_ref().map_or(0, |details| details.cached_tokens)
+    }
+
+    ///completion tokens spent on reasoning, that are not part of the answer
+    pub fn reasoning_tokens(&self) -> u64 {
+        self.completion_tokens_details.as_ref().map_or(0, |details| details.reasoning_tokens)
+    }
+}
+
+///breakdown of prompt tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+    #[serde(default)]
+    pub audio_tokens: u64
+}
+
+///breakdown of completion tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CompletionTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+    #[serde(default)]
+    pub audio_tokens: u64,
+    ///predicted output tokens, that appeared in completion
+    #[serde(default)]
+    pub accepted_prediction_tokens: u64,
+    ///predicted output tokens, that did not appear in completion, they are still billed
+    #[serde(default)]
+    pub rejected_prediction_tokens: u64
+}
+
+///usage as reported by any endpoint, embeddings do not report completion tokens
+#[derive(Deserialize)]
+pub(crate) struct ReportedUsage {
+    pub(crate) usage: Option<TokenCounts>
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) struct TokenCounts {
+    #[serde(default)]
+    pub(crate) prompt_tokens: u64,
+    pub(crate) completion_tokens: Option<u64>,
+    pub(crate) total_tokens: u64
 }
 
 #[async_trait]
@@ -186,15 +580,64 @@ pub trait JsonRequest<TRes: DeserializeOwned>: Serialize + Sized + Sync{
 
     const ENDPOINT: &'static str;
 
+    ///checks request before it is sent, nothing is checked by default
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_response(&self, client:&OpenAiClient) -> Result<Response>{
+        self.validate()?;
+        let final_url = client.url_for(Self::ENDPOINT, "", || request_model(self));
+        let reservation = Reservation::acquire(&client.limiter, self).await;
+        let mut res = retry::send(client, || async {
+            Ok(client.client.post(&final_url)
+                .json(self))
+        }).await?;
+        if let Some(reservation) = reservation {
+            res.extensions_mut().insert(reservation);
+        }
+        Ok(res)
+    }
+
     async fn run(&self, client:&OpenAiClient) -> Result<TRes>{
-        let final_url = client.url.to_owned()+Self::ENDPOINT;
-        let res = client.client.post(final_url)
-            .bearer_auth(client.key.clone())
-            .json(self)
-            .send()
-            .await?;
+        let res = self.get_response(client).await?;
         process_response::<TRes>(res).await
     }
+
+    ///same as `run`, but also returns request id, timing and rate limit state
+    async fn run_with_meta(&self, client:&OpenAiClient) -> Result<WithMeta<TRes>>{
+        let res = self.get_response(client).await?;
+        process_response_with_meta::<TRes>(res).await
+    }
+}
+
+///stream of typed events, returned by `StreamingRequest::run_stream`
+pub type EventStream<T> = Pin<Box<dyn Stream<Item=Result<T>> + Send>>;
+
+///requests that can be answered with server-sent events instead of single json body.
+///`stream` parameter is set to `true` automatically, whatever value request holds.
+#[async_trait]
+pub trait StreamingRequest<TChunk: DeserializeOwned + Send + 'static>: Serialize + Sized + Sync{
+
+    const ENDPOINT: &'static str;
+
+    ///checks request before it is sent, nothing is checked by default
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn run_stream(&self, client:&OpenAiClient) -> Result<EventStream<TChunk>>{
+        self.validate()?;
+        let mut body = serde_json::to_value(self).map_err(OpenAiError::Serialization)?;
+        body["stream"] = serde_json::Value::Bool(true);
+        let final_url = client.url_for(Self::ENDPOINT, "", || request_model(&body));
+        let reservation = Reservation::acquire(&client.limiter, &body).await;
+        let res = retry::send(client, || async {
+            Ok(client.client.post(&final_url)
+                .json(&body))
+        }).await?;
+        process_stream_response::<TChunk>(res, reservation).await
+    }
 }
 
 #[async_trait]
@@ -207,14 +650,23 @@ pub trait ByUrlRequest<TRes: DeserializeOwned>:WithRefId<str>+Sync{
         client.client.get(final_url)
     }
 
+    async fn get_response(&self, client:&OpenAiClient)-> Result<Response>{
+        let final_url = client.url_for(Self::ENDPOINT, &(self.id().to_owned()+Self::SUFFIX), || None);
+        retry::send(client, || async {
+            Ok(Self::builder(client,final_url.clone()))
+        }).await
+    }
+
     async fn run(&self, client:&OpenAiClient)-> Result<TRes>{
-        let final_url = client.url.to_owned()+Self::ENDPOINT+self.id()+Self::SUFFIX;
-        let res = Self::builder(client,final_url)
-            .bearer_auth(client.key.clone())
-            .send()
-            .await?;
+        let res = self.get_response(client).await?;
         process_response::<TRes>(res).await
     }
+
+    ///same as `run`, but also returns request id, timing and rate limit state
+    async fn run_with_meta(&self, client:&OpenAiClient)-> Result<WithMeta<TRes>>{
+        let res = self.get_response(client).await?;
+        process_response_with_meta::<TRes>(res).await
+    }
 }
 
 
@@ -223,38 +675,53 @@ pub trait GetRequest:DeserializeOwned {
 
     const ENDPOINT: &'static str;
 
+    async fn get_response(client:&OpenAiClient)-> Result<Response>{
+        let final_url = client.url_for(Self::ENDPOINT, "", || None);
+        retry::send(client, || async {
+            Ok(client.client.get(&final_url))
+        }).await
+    }
+
     async fn get(client:&OpenAiClient)-> Result<Self>{
-        let final_url = client.url.to_owned()+Self::ENDPOINT;
-        let res = client.client.get(final_url)
-            .bearer_auth(client.key.clone())
-            .send()
-            .await?;
+        let res = Self::get_response(client).await?;
         process_response::<Self>(res).await
     }
+
+    ///same as `get`, but also returns request id, timing and rate limit state
+    async fn get_with_meta(client:&OpenAiClient)-> Result<WithMeta<Self>>{
+        let res = Self::get_response(client).await?;
+        process_response_with_meta::<Self>(res).await
+    }
 }
 
 #[async_trait]
-pub trait FormRequest<TRes: DeserializeOwned> : AsyncTryInto<multipart::Form>+Clone+Sync+Send {
+pub trait FormRequest<TRes: DeserializeOwned> : AsyncTryInto<multipart::Form,Error=io::Error>+Clone+Sync+Send {
 
     const ENDPOINT: &'static str;
 
+    ///sends the form, multipart body is rebuilt from the files on every retry attempt
     async fn get_response(&self,
-                          client:&Client,
-                          final_url:String,
-                          key:&str
+                          client:&OpenAiClient,
+                          final_url:String
     ) -> Result<Response> {
-        client.post(final_url)
-            .bearer_auth(key.clone())
-            .multipart(AsyncTryInto::try_into(self.clone()).await?)
-            .send()
-            .await.map_err(anyhow::Error::new)
+        retry::send(client, || async {
+            Ok(client.client.post(&final_url)
+                .multipart(AsyncTryInto::try_into(self.clone()).await?))
+        }).await
     }
 
     async fn run(&self, client:&OpenAiClient)-> Result<TRes>{
-        let final_url =  client.url.to_owned()+Self::ENDPOINT;
-        let res = self.get_response(&client.client,final_url,&client.key).await?;
+        let final_url = client.url_for(Self::ENDPOINT, "", || None);
+        let res = self.get_response(client,final_url).await?;
         process_response::<TRes>(res).await
     }
+
+    ///same as `run`, but also returns request id, timing and rate limit state
+    async fn run_with_meta(&self, client:&OpenAiClient)-> Result<WithMeta<TRes>>{
+        let final_url = client.url_for(Self::ENDPOINT, "", || None);
+        let res = self.get_response(client,final_url).await?;
+        process_response_with_meta::<TRes>(res).await
+    }
 }
 
 #[async_trait(?Send)]
@@ -263,25 +730,20 @@ pub trait DownloadRequest: WithRefId<str>{
     const ENDPOINT: &'static str;
     const SUFFIX: &'static str = "";
 
-    async fn download(&self, client:&OpenAiClient) -> Result<Pin<Box<dyn Stream<Item=Result<Bytes, reqwest::Error>>>>>{
-        let final_url = client.url.to_owned()+Self::ENDPOINT+self.id()+Self::SUFFIX;
-        let res = client.client.get(final_url)
-            .bearer_auth(client.key.clone())
-            .send()
-            .await?;
-        let code = res.error_for_status_ref();
-        return match code {
-            Ok(_) => Ok(Box::pin(res.bytes_stream())),
-            Err(err) =>
-                Err(Error {
-                    response: res.json::<ApiError>().await?,
-                    inner: err
-                })?
+    async fn download(&self, client:&OpenAiClient) -> Result<Pin<Box<dyn Stream<Item=Result<Bytes>>>>>{
+        let final_url = client.url_for(Self::ENDPOINT, &(self.id().to_owned()+Self::SUFFIX), || None);
+        let res = retry::send(client, || async {
+            Ok(client.client.get(&final_url))
+        }).await?;
+        if res.status().is_success() {
+            Ok(Box::pin(res.bytes_stream().map(|chunk| chunk.map_err(OpenAiError::from))))
+        } else {
+            Err(OpenAiError::from_response(res).await)
         }
     }
 
     async fn download_to_file(&self, client:&OpenAiClient, target_path:&str) -> Result<()>{
-        let file = File::create(target_path).map_err(anyhow::Error::new);
+        let file = File::create(target_path).map_err(OpenAiError::from);
         let stream = self.download(client);
         let (mut file, mut stream) = try_join!(file, stream)?;
         while let Some(chunk) = stream.next().await {
@@ -292,36 +754,61 @@ pub trait DownloadRequest: WithRefId<str>{
 
 }
 
-pub(crate) async fn process_response<T:DeserializeOwned>(response: Response) ->Result<T>{
-    let code = response.error_for_status_ref();
-    match code {
-        Ok(_) =>{
-            let full = response.text().await?;
-            dbg!(&full);
-            serde_json::from_str(&full)
-                .map_err(|err| anyhow::Error::new(err).context(full))
+///`model` field of json request, used to pick Azure deployment
+fn request_model<T: Serialize>(request: &T) -> Option<String> {
+    serde_json::to_value(request).ok()?
+        .get("model")?
+        .as_str()
+        .map(str::to_string)
+}
+
+pub(crate) async fn process_response<T:DeserializeOwned>(mut response: Response) ->Result<T>{
+    let span = RequestSpan::take(&mut response);
+    let reservation = response.extensions_mut().remove::<Reservation>();
+    if !response.status().is_success() {
+        if let Some(reservation) = reservation {
+            reservation.settle(None);
+        }
+        let err = OpenAiError::from_response(response).await;
+        if let Some(span) = span {
+            span.record_error(&err);
         }
-        Err(err) =>
-            Err(Error {
-                response: response.json::<ApiError>().await?,
-                inner: err
-            })?
+        return Err(err)
     }
+    let full = response.text().await?;
+    if let Some(reservation) = reservation {
+        reservation.settle(Some(&full));
+    }
+    if let Some(span) = span {
+        span.record_body(&full);
+    }
+    serde_json::from_str(&full)
+        .map_err(|source| OpenAiError::Deserialization { body: full, source })
 }
 
+pub(crate) async fn process_response_with_meta<T:DeserializeOwned>(response: Response) ->Result<WithMeta<T>>{
+    let meta = ResponseMeta::from_headers(response.status(), response.headers());
+    let body = process_response::<T>(response).await?;
+    Ok(WithMeta { body, meta })
+}
 
-pub(crate) async fn process_text_response(response: Response) ->Result<String>{
-    let code = response.error_for_status_ref();
-    match code {
-        Ok(_) =>{
-            response.text().await.map_err(anyhow::Error::new)
+///Streamed responses only report usage in the last chunk, when it is requested with `include_usage`.
+///Reservation is settled with it then, otherwise estimate is kept
+pub(crate) async fn process_stream_response<T:DeserializeOwned + Send + 'static>(response: Response, reservation: Option<Reservation>) ->Result<EventStream<T>>{
+    if !response.status().is_success() {
+        if let Some(reservation) = reservation {
+            reservation.settle(None);
         }
-        Err(err) =>
-            Err(Error {
-                response: response.json::<ApiError>().await?,
-                inner: err
-            })?
+        return Err(OpenAiError::from_response(response).await)
+    }
+    Ok(sse::response_events::<T>(response, reservation))
+}
+
+pub(crate) async fn process_text_response(response: Response) ->Result<String>{
+    if !response.status().is_success() {
+        return Err(OpenAiError::from_response(response).await)
     }
+    Ok(response.text().await?)
 }
 
 
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde_json::Value;
+use crate::ReportedUsage;
+
+///requests-per-minute and tokens-per-minute budget for a model
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModelLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>
+}
+
+impl ModelLimits {
+
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        ModelLimits {
+            requests_per_minute: Some(requests_per_minute),
+            tokens_per_minute: Some(tokens_per_minute)
+        }
+    }
+
+    pub fn requests(requests_per_minute: u32) -> Self {
+        ModelLimits {
+            requests_per_minute: Some(requests_per_minute),
+            tokens_per_minute: None
+        }
+    }
+
+    pub fn tokens(tokens_per_minute: u32) -> Self {
+        ModelLimits {
+            requests_per_minute: None,
+            tokens_per_minute: Some(tokens_per_minute)
+        }
+    }
+}
+
+///Client side limiter, that keeps requests within per-model RPM and TPM budgets.
+///Callers that would exceed budget are not failed, they wait in a queue until budget
+///is replenished. Token cost of each request is estimated from its payload before sending,
+///and corrected with `usage` reported in response.
+///
+///Limiter applies to json requests that carry `model` field (chat, completions, embeddings, etc.).
+///Budget is taken once per request, attempts repeated by client's retry policy do not take it again.
+/// # Usage example
+/// ```
+/// use openai_req::OpenAiClient;
+/// use openai_req::limiter::{ModelLimits, RateLimiter};
+///
+/// let limiter = RateLimiter::new()
+///     .model("gpt-3.5-turbo", ModelLimits::new(3_500, 90_000))
+///     .model("text-embedding-ada-002", ModelLimits::new(3_000, 1_000_000));
+/// let client = OpenAiClient::new("{YOUR_API_KEY}").rate_limiter(limiter);
+/// ```
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: HashMap<String, ModelLimits>,
+    default_limits: Option<ModelLimits>,
+    buckets: Mutex<HashMap<String, Arc<Bucket>>>
+}
+
+impl RateLimiter {
+
+    ///limiter without any limits, add them with `model` and `default_limits`
+    pub fn new() -> Self {
+        RateLimiter::default()
+    }
+
+    ///sets limits for specific model
+    pub fn model(mut self, model: &str, limits: ModelLimits) -> Self {
+        self.limits.insert(model.to_string(), limits);
+        self
+    }
+
+    ///sets limits for models, that have no limits set explicitly
+    pub fn default_limits(mut self, limits: ModelLimits) -> Self {
+        self.default_limits = Some(limits);
+        self
+    }
+
+    fn bucket(&self, model: &str) -> Option<Arc<Bucket>> {
+        let limits = self.limits.get(model).or(self.default_limits.as_ref())?;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(model.to_string())
+            .or_insert_with(|| Arc::new(Bucket::new(*limits)));
+        Some(bucket.clone())
+    }
+
+    ///waits until model has budget for one request costing `tokens`, and takes it.
+    ///Callers are served in order of arrival
+    pub async fn acquire(&self, model: &str, tokens: u64) {
+        let bucket = match self.bucket(model) {
+            Some(bucket) => bucket,
+            None => return
+        };
+        let _turn = bucket.queue.lock().await;
+        loop {
+            let wait = bucket.state.lock().unwrap().try_take(tokens);
+            match wait {
+                None => return,
+                Some(wait) => tokio::time::sleep(wait).await
+            }
+        }
+    }
+
+    ///corrects token budget after actual usage of the request became known
+    pub fn settle(&self, model: &str, estimated: u64, actual: u64) {
+        if let Some(bucket) = self.bucket(model) {
+            bucket.state.lock().unwrap().adjust(estimated as f64 - actual as f64);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    ///held by the caller that is currently waiting for budget, others queue behind it
+    queue: tokio::sync::Mutex<()>,
+    state: Mutex<BucketState>
+}
+
+impl Bucket {
+    fn new(limits: ModelLimits) -> Self {
+        Bucket {
+            queue: tokio::sync::Mutex::new(()),
+            state: Mutex::new(BucketState {
+                limits,
+                requests: limits.requests_per_minute.unwrap_or(0) as f64,
+                tokens: limits.tokens_per_minute.unwrap_or(0) as f64,
+                updated: Instant::now()
+            })
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BucketState {
+    limits: ModelLimits,
+    requests: f64,
+    tokens: f64,
+    updated: Instant
+}
+
+impl BucketState {
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let minutes = now.duration_since(self.updated).as_secs_f64() / 60.0;
+        self.updated = now;
+        if let Some(rpm) = self.limits.requests_per_minute {
+            self.requests = (self.requests + rpm as f64 * minutes).min(rpm as f64);
+        }
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            self.tokens = (self.tokens + tpm as f64 * minutes).min(tpm as f64);
+        }
+    }
+
+    ///takes budget for one request, or returns how long to wait until it is available
+    fn try_take(&mut self, tokens: u64) -> Option<Duration> {
+        self.refill();
+        let mut wait_minutes = 0f64;
+        if let Some(rpm) = self.limits.requests_per_minute {
+            if self.requests < 1.0 {
+                wait_minutes = wait_minutes.max((1.0 - self.requests) / rpm.max(1) as f64);
+            }
+        }
+        //request larger than whole budget is let through once budget is full
+        let tokens = self.limits.tokens_per_minute
+            .map(|tpm| (tokens as f64).min(tpm as f64))
+            .unwrap_or(0.0);
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            if self.tokens < tokens {
+                wait_minutes = wait_minutes.max((tokens - self.tokens) / tpm.max(1) as f64);
+            }
+        }
+        if wait_minutes > 0.0 {
+            return Some(Duration::from_secs_f64(wait_minutes * 60.0).max(Duration::from_millis(1)))
+        }
+        self.requests -= 1.0;
+        self.tokens -= tokens;
+        None
+    }
+
+    fn adjust(&mut self, tokens: f64) {
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            self.tokens = (self.tokens + tokens).min(tpm as f64);
+        }
+    }
+}
+
+///Rough token estimate for json request payload: about 4 characters per token
+///for all prompt text, plus per-message overhead and requested completion size.
+pub fn estimate_tokens(payload: &Value) -> u64 {
+    let mut chars = 0u64;
+    let mut messages = 0;
+    for field in ["prompt", "input", "instruction", "suffix"] {
+        if let Some(value) = payload.get(field) {
+            chars += text_length(value);
+        }
+    }
+    if let Some(Value::Array(items)) = payload.get("messages") {
+        messages = items.len() as u64;
+        chars += items.iter().map(text_length).sum::<u64>();
+    }
+    let prompt = chars.div_ceil(4) + messages * 4;
+    let n = payload.get("n").and_then(Value::as_u64).unwrap_or(1);
+    let completion = payload.get("max_tokens")
+        .or(payload.get("max_completion_tokens"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    prompt + completion * n
+}
+
+fn text_length(value: &Value) -> u64 {
+    match value {
+        Value::String(text) => text.chars().count() as u64,
+        Value::Array(items) => items.iter().map(text_length).sum(),
+        //inline images and audio are not text, and would inflate estimate
+        Value::Object(fields) => fields.iter()
+            .filter(|(name, _)| !matches!(name.as_str(), "image_url" | "input_audio"))
+            .map(|(_, value)| text_length(value))
+            .sum(),
+        _ => 0
+    }
+}
+
+///budget taken by request, that is corrected once response arrives
+#[derive(Debug, Clone)]
+pub(crate) struct Reservation {
+    limiter: Arc<RateLimiter>,
+    model: String,
+    estimated: u64
+}
+
+impl Reservation {
+
+    ///waits for budget, if client has limiter and request has model
+    pub(crate) async fn acquire<T: Serialize>(limiter: &Option<Arc<RateLimiter>>, request: &T) -> Option<Reservation> {
+        let limiter = limiter.as_ref()?;
+        let payload = serde_json::to_value(request).ok()?;
+        let model = payload.get("model")?.as_str()?.to_string();
+        let estimated = estimate_tokens(&payload);
+        limiter.acquire(&model, estimated).await;
+        Some(Reservation { limiter: limiter.clone(), model, estimated })
+    }
+
+    ///corrects estimate with usage reported in response body.
+    ///Requests that failed or have no usage reported do not consume tokens
+    pub(crate) fn settle(self, body: Option<&str>) {
+        let actual = body
+            .and_then(|body| serde_json::from_str::<ReportedUsage>(body).ok())
+            .and_then(|response| response.usage)
+            .map(|usage| usage.total_tokens)
+            .unwrap_or(0);
+        self.limiter.settle(&self.model, self.estimated, actual);
+    }
+}
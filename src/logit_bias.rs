@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+#[cfg(feature = "tokenizer")]
+use crate::tokenizer::Tokenizer;
+
+///Builder for `logit_bias` parameter of chat and completion requests.
+///Bias is clamped to [-100, 100]: -100 bans token, 100 makes it the only choice,
+///values around ±1 slightly change its likelihood.
+///
+///With `tokenizer` feature, words and phrases can be biased directly,
+///they are split into tokens with the encoding of the model.
+/// # Usage example
+/// ```ignore
+/// use openai_req::chat::ChatRequest;
+/// use openai_req::logit_bias::LogitBias;
+///
+/// let bias = LogitBias::for_model("gpt-4o")
+///     .ban("delve")
+///     .word("Rust", 5.0);
+/// let chat_request = ChatRequest::new(messages).logit_bias(bias);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LogitBias {
+    #[cfg(feature = "tokenizer")]
+    model: String,
+    biases: HashMap<u32, f32>
+}
+
+impl LogitBias {
+
+    ///empty bias, tokens are added by id
+    pub fn new() -> Self {
+        LogitBias::default()
+    }
+
+    pub fn token(mut self, token: u32, bias: f32) -> Self {
+        self.biases.insert(token, bias.clamp(-100.0, 100.0));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biases.is_empty()
+    }
+}
+
+#[cfg(feature = "tokenizer")]
+impl LogitBias {
+
+    ///bias, that tokenizes words with encoding of the model
+    pub fn for_model(model: &str) -> Self {
+        LogitBias { model: model.to_string(), biases: HashMap::new() }
+    }
+
+    ///Biases all tokens of the word, both at the start of the text and after space,
+    ///as those are different tokens. Tokens of multi-token words are biased individually,
+    ///so banning such word also affects other words that share its tokens
+    pub fn word(self, word: &str, bias: f32) -> Self {
+        let word = word.trim();
+        self.phrase(word, bias).phrase(&format!(" {}", word), bias)
+    }
+
+    ///bans the word, see `word`
+    pub fn ban(self, word: &str) -> Self {
+        self.word(word, -100.0)
+    }
+
+    ///biases tokens of the text exactly as given, without adding leading space variant
+    pub fn phrase(self, text: &str, bias: f32) -> Self {
+        let tokens = Tokenizer::for_model(&self.model).encode(text);
+        tokens.into_iter().fold(self, |logit_bias, token| logit_bias.token(token, bias))
+    }
+}
+
+impl From<LogitBias> for HashMap<String, f32> {
+    fn from(value: LogitBias) -> Self {
+        value.biases.into_iter()
+            .map(|(token, bias)| (token.to_string(), bias))
+            .collect()
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+///Log probabilities of generated tokens, returned when `logprobs` is requested.
+///Helpers turn them into probabilities, for example to route uncertain answers for review:
+/// # Usage example
+/// ```ignore
+/// use openai_req::logprobs::TokenProbabilities;
+///
+/// let chat_request = ChatRequest::new(messages).top_logprobs(3);
+/// let response = chat_request.run(&client).await?;
+/// let logprobs = response.choices[0].logprobs.as_ref().unwrap();
+/// if logprobs.min_confidence().unwrap_or(0.0) < 0.5 {
+///     //send to human
+/// }
+/// ```
+pub trait TokenProbabilities {
+
+    ///generated tokens with their natural log probabilities, in order
+    fn token_logprobs(&self) -> Vec<(&str, f64)>;
+
+    ///log probability of the whole generated sequence
+    fn sequence_logprob(&self) -> f64 {
+        self.token_logprobs().iter().map(|(_, logprob)| logprob).sum()
+    }
+
+    ///probability of the whole generated sequence, gets small quickly for long answers
+    fn sequence_probability(&self) -> f64 {
+        self.sequence_logprob().exp()
+    }
+
+    ///probability of each generated token, from 0 to 1
+    fn token_confidences(&self) -> Vec<(&str, f64)> {
+        self.token_logprobs().into_iter()
+            .map(|(token, logprob)| (token, logprob.exp()))
+            .collect()
+    }
+
+    ///probability of the least certain token, `None` if there are no tokens
+    fn min_confidence(&self) -> Option<f64> {
+        self.token_logprobs().iter()
+            .map(|(_, logprob)| logprob.exp())
+            .reduce(f64::min)
+    }
+
+    ///geometric mean of token probabilities, unlike sequence probability it does not depend on length
+    fn mean_confidence(&self) -> Option<f64> {
+        let logprobs = self.token_logprobs();
+        if logprobs.is_empty() {
+            return None
+        }
+        Some((self.sequence_logprob() / logprobs.len() as f64).exp())
+    }
+}
+
+///logprobs of completion choice, all lists are aligned by token
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct CompletionLogprobs {
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    ///first token has no logprob when prompt is echoed
+    #[serde(default)]
+    pub token_logprobs: Vec<Option<f64>>,
+    ///most likely alternatives at each position, with their logprobs
+    #[serde(default)]
+    pub top_logprobs: Option<Vec<Option<HashMap<String, f64>>>>,
+    ///position of each token in the text
+    #[serde(default)]
+    pub text_offset: Vec<u32>
+}
+
+impl CompletionLogprobs {
+
+    ///appends logprobs of the next streamed fragment
+    pub fn extend(&mut self, other: CompletionLogprobs) {
+        self.tokens.extend(other.tokens);
+        self.token_logprobs.extend(other.token_logprobs);
+        self.text_offset.extend(other.text_offset);
+        if let Some(top_logprobs) = other.top_logprobs {
+            self.top_logprobs.get_or_insert_with(Vec::new).extend(top_logprobs);
+        }
+    }
+}
+
+impl TokenProbabilities for CompletionLogprobs {
+    fn token_logprobs(&self) -> Vec<(&str, f64)> {
+        self.tokens.iter()
+            .zip(self.token_logprobs.iter())
+            .filter_map(|(token, logprob)| logprob.map(|logprob| (token.as_str(), logprob)))
+            .collect()
+    }
+}
+
+///logprobs of chat choice
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ChatLogprobs {
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+    #[serde(default)]
+    pub refusal: Option<Vec<TokenLogprob>>
+}
+
+impl ChatLogprobs {
+
+    ///appends logprobs of the next streamed chunk
+    pub fn extend(&mut self, other: ChatLogprobs) {
+        if let Some(content) = other.content {
+            self.content.get_or_insert_with(Vec::new).extend(content);
+        }
+        if let Some(refusal) = other.refusal {
+            self.refusal.get_or_insert_with(Vec::new).extend(refusal);
+        }
+    }
+}
+
+impl TokenProbabilities for ChatLogprobs {
+    ///tokens of the content, refusal tokens are not included
+    fn token_logprobs(&self) -> Vec<(&str, f64)> {
+        self.content.iter().flatten()
+            .map(|token| (token.token.as_str(), token.logprob))
+            .collect()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    ///UTF-8 bytes of the token, useful when character is split between tokens
+    pub bytes: Option<Vec<u8>>,
+    ///most likely alternatives at this position, requested with `top_logprobs`
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use crate::retry::parse_duration;
+
+///response body together with metadata taken from response headers,
+///returned by `run_with_meta` methods
+#[derive(Debug, Clone)]
+pub struct WithMeta<T> {
+    pub body: T,
+    pub meta: ResponseMeta
+}
+
+///metadata that API sends in response headers
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    pub status: StatusCode,
+    ///`x-request-id`, quote it when contacting support
+    pub request_id: Option<String>,
+    ///`openai-processing-ms`, time API spent processing request
+    pub processing_ms: Option<u64>,
+    ///`openai-organization`, organization request was billed to
+    pub organization: Option<String>,
+    ///`openai-model`, model that served the request
+    pub model: Option<String>,
+    pub rate_limit: RateLimitInfo
+}
+
+///state of the rate limits after request, from `x-ratelimit-*` headers
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitInfo {
+    pub limit_requests: Option<u64>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    ///time until request limit is fully replenished
+    pub reset_requests: Option<Duration>,
+    ///time until token limit is fully replenished
+    pub reset_tokens: Option<Duration>
+}
+
+impl ResponseMeta {
+
+    pub fn from_headers(status: StatusCode, headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let number = |name: &str| header(name).and_then(|value| value.parse::<u64>().ok());
+        let duration = |name: &str| header(name).and_then(|value| parse_duration(&value));
+        ResponseMeta {
+            status,
+            request_id: header("x-request-id"),
+            processing_ms: number("openai-processing-ms"),
+            organization: header("openai-organization"),
+            model: header("openai-model"),
+            rate_limit: RateLimitInfo {
+                limit_requests: number("x-ratelimit-limit-requests"),
+                limit_tokens: number("x-ratelimit-limit-tokens"),
+                remaining_requests: number("x-ratelimit-remaining-requests"),
+                remaining_tokens: number("x-ratelimit-remaining-tokens"),
+                reset_requests: duration("x-ratelimit-reset-requests"),
+                reset_tokens: duration("x-ratelimit-reset-tokens")
+            }
+        }
+    }
+}
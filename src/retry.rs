@@ -0,0 +1,256 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use crate::{OpenAiClient, OpenAiError, Result};
+use crate::trace::RequestSpan;
+
+///Policy that decides if and when failed request is repeated.
+///Delay between attempts grows exponentially from `base_delay` up to `max_delay`,
+///unless API tells how long to wait with `Retry-After` or `x-ratelimit-reset-*` headers,
+///in which case it waits as long as requested.
+/// # Usage example
+/// ```
+/// use std::time::Duration;
+/// use openai_req::OpenAiClient;
+/// use openai_req::retry::RetryPolicy;
+///
+/// let policy = RetryPolicy::default()
+///     .max_attempts(5)
+///     .base_delay(Duration::from_secs(1));
+/// let client = OpenAiClient::new("{YOUR_API_KEY}").retry_policy(policy);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_on: Vec<StatusCode>,
+    retry_transport_errors: bool,
+    respect_headers: bool
+}
+
+impl Default for RetryPolicy {
+    ///3 attempts, starting with half a second delay, capped at 30 seconds,
+    ///retries on 408, 429 and 5xx gateway statuses
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_on: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT
+            ],
+            retry_transport_errors: true,
+            respect_headers: true
+        }
+    }
+}
+
+impl RetryPolicy {
+
+    ///policy that sends every request exactly once
+    pub fn none() -> Self {
+        RetryPolicy::default().max_attempts(1)
+    }
+
+    ///total number of attempts, including the first one
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    ///cap of backoff delay, delays requested by API headers are not capped
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    ///randomizes each backoff delay between half and full value,
+    ///so concurrent clients do not retry all at once
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    ///replaces list of statuses that cause retry
+    pub fn retry_on(mut self, retry_on: Vec<StatusCode>) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    ///whether connection errors and timeouts are retried
+    pub fn retry_transport_errors(mut self, retry_transport_errors: bool) -> Self {
+        self.retry_transport_errors = retry_transport_errors;
+        self
+    }
+
+    ///whether delay requested by API headers takes precedence over backoff
+    pub fn respect_headers(mut self, respect_headers: bool) -> Self {
+        self.respect_headers = respect_headers;
+        self
+    }
+
+    ///whether response with this status is repeated
+    pub fn retries(&self, status: StatusCode) -> bool {
+        self.retry_on.contains(&status)
+    }
+
+    ///backoff delay after failed attempt number `attempt`, counting from 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+
+    ///Delay after failed attempt, which returned response with these headers.
+    ///Delay requested by API is not capped by `max_delay`, since repeating earlier would fail again,
+    ///but values that are not a valid duration (like `inf`) are replaced with `max_delay`
+    pub fn delay_for(&self, attempt: u32, headers: &HeaderMap) -> Duration {
+        if self.respect_headers {
+            if let Some(delay) = requested_delay(headers, self.max_delay) {
+                return delay
+            }
+        }
+        self.backoff(attempt)
+    }
+}
+
+///delay that API asked to wait before next request, if any.
+///Numbers too large for `Duration` are clamped to `max_delay`
+fn requested_delay(headers: &HeaderMap, max_delay: Duration) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let seconds = |secs: f64| (!secs.is_nan())
+        .then(|| Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(max_delay));
+    if let Some(millis) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return seconds(millis / 1000.0)
+    }
+    if let Some(secs) = header(RETRY_AFTER.as_str()).and_then(|value| value.parse::<f64>().ok()) {
+        return seconds(secs)
+    }
+    //only wait for limits that are actually exhausted
+    ["requests", "tokens"].iter()
+        .filter(|limit| header(&format!("x-ratelimit-remaining-{}", limit)) == Some("0"))
+        .filter_map(|limit| header(&format!("x-ratelimit-reset-{}", limit)).and_then(parse_duration))
+        .max()
+}
+
+///parses durations in format used by rate limit headers, like `1s`, `6m0s`, `20ms` or `1h2m3.5s`.
+///Values too large for `Duration` are not parsed
+pub(crate) fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0f64;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    let mut parsed_any = false;
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue
+        }
+        let amount = number.parse::<f64>().ok()?;
+        number.clear();
+        let multiplier = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None
+        };
+        total += amount * multiplier;
+        parsed_any = true;
+    }
+    if !number.is_empty() {
+        //bare number is seconds
+        total += number.parse::<f64>().ok()?;
+        parsed_any = true;
+    }
+    Duration::try_from_secs_f64(total).ok().filter(|_| parsed_any)
+}
+
+///sends request built by `request`, repeating it according to client's retry policy.
+///Request is rebuilt for every attempt, so bodies that can only be sent once
+///(like multipart forms streamed from file) are recreated.
+pub(crate) async fn send<F, Fut>(client: &OpenAiClient, request: F) -> Result<Response>
+    where F: Fn() -> Fut,
+          Fut: Future<Output=Result<RequestBuilder>>
+{
+    let policy = &client.retry;
+    let mut span = None;
+    let mut attempt = 0;
+    loop {
+        let last_attempt = attempt + 1 >= policy.max_attempts;
+        let (request, credential) = client.authorize(request().await?).await?;
+        let (http, built) = request
+            .headers(client.headers.clone())
+            .build_split();
+        let built = built?;
+        let span = span.get_or_insert_with(|| RequestSpan::new(&built, credential.secret()));
+        let started = Instant::now();
+        match http.execute(built).await {
+            Ok(mut response) => {
+                span.record_attempt(attempt, Some(response.status()), started.elapsed());
+                if last_attempt || !policy.retries(response.status()) {
+                    span.clone().attach(&mut response);
+                    return Ok(response)
+                }
+                tokio::time::sleep(policy.delay_for(attempt, response.headers())).await;
+            }
+            Err(err) => {
+                span.record_attempt(attempt, err.status(), started.elapsed());
+                if last_attempt || !policy.retry_transport_errors || !(err.is_connect() || err.is_timeout()) {
+                    span.record_error(&err);
+                    return Err(err.into())
+                }
+                tokio::time::sleep(policy.backoff(attempt)).await;
+            }
+        }
+        attempt += 1;
+    }
+}
+
+///Repeats whole operation, like sending request and reading its body, while it fails
+///with transport error after response status was received. Statuses and connection errors
+///are already repeated by `send`, so they are returned as is. Used for batches,
+///where losing a response body would fail all texts.
+pub(crate) async fn repeat<F, Fut, T>(policy: &RetryPolicy, operation: F) -> Result<T>
+    where F: Fn() -> Fut,
+          Fut: Future<Output=Result<T>>
+{
+    let mut attempt = 0;
+    loop {
+        let err = match operation().await {
+            Ok(result) => return Ok(result),
+            Err(err) => err
+        };
+        let retryable = match &err {
+            OpenAiError::Transport(err) => policy.retry_transport_errors && (err.is_body() || err.is_decode()),
+            _ => false
+        };
+        if attempt + 1 >= policy.max_attempts || !retryable {
+            return Err(err)
+        }
+        tokio::time::sleep(policy.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
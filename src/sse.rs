@@ -0,0 +1,147 @@
+use std::pin::Pin;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::stream;
+use reqwest::Response;
+use serde::de::DeserializeOwned;
+use tokio_stream::{Stream, StreamExt};
+use crate::{ApiError, EventStream, OpenAiError, ReportedUsage, Result};
+use crate::limiter::Reservation;
+
+const DONE: &str = "[DONE]";
+
+struct SseState {
+    bytes: Pin<Box<dyn Stream<Item=reqwest::Result<Bytes>> + Send>>,
+    buffer: BytesMut,
+    closed: bool,
+    finished: bool,
+    reservation: Option<Reservation>
+}
+
+impl SseState {
+
+    ///pops next complete event from the buffer and returns its joined `data:` lines.
+    ///events without data (comments, keep-alives) are skipped
+    fn next_data(&mut self) -> Option<String> {
+        while let Some(end) = find_event_end(&self.buffer) {
+            let raw = self.buffer.split_to(end.0);
+            self.buffer.advance(end.1);
+            let data = String::from_utf8_lossy(&raw)
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<&str>>()
+                .join("\n");
+            if !data.is_empty() {
+                return Some(data)
+            }
+        }
+        None
+    }
+
+    ///settles limiter reservation with usage, that is sent in the last chunk when `include_usage` is requested.
+    ///Without it, estimate is kept
+    fn settle_usage(&mut self, data: &str) {
+        if self.reservation.is_none() || !data.contains("\"usage\"") {
+            return
+        }
+        let reported = serde_json::from_str::<ReportedUsage>(data).is_ok_and(|response| response.usage.is_some());
+        if let Some(reservation) = self.reservation.take_if(|_| reported) {
+            reservation.settle(Some(data));
+        }
+    }
+}
+
+///finds end of the first event in buffer,
+///returns event length and length of the blank line separator after it
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < buffer.len() {
+        for separator in [&b"\r\n\r\n"[..], b"\n\n", b"\r\r"] {
+            if buffer[i..].starts_with(separator) {
+                return Some((i, separator.len()))
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+///parses event data into expected type,
+///or into `OpenAiError::Stream` if API reported an error in the middle of the stream
+fn parse_event<T: DeserializeOwned>(data: String) -> Result<T> {
+    match serde_json::from_str::<T>(&data) {
+        Ok(event) => Ok(event),
+        Err(source) => match serde_json::from_str::<ApiError>(&data) {
+            Ok(api_error) => Err(OpenAiError::Stream { details: api_error.error }),
+            Err(_) => Err(OpenAiError::Deserialization { body: data, source })
+        }
+    }
+}
+
+///turns body of server-sent-event response into stream of typed events
+pub(crate) fn response_events<T: DeserializeOwned + Send + 'static>(response: Response, reservation: Option<Reservation>) -> EventStream<T> {
+    events(response.bytes_stream(), reservation)
+}
+
+///Turns stream of server-sent-event body bytes into stream of typed events, parsed from `data:` lines.
+///Events may be split across chunks in any way. Stream ends when `[DONE]` message is received,
+///error reported by API in the middle of the stream comes as `OpenAiError::Stream`.
+/// # Usage example
+/// ```ignore
+/// use openai_req::chat::ChatChunk;
+/// use openai_req::sse::event_stream;
+///
+/// let response = reqwest::get(proxy_url).await?;
+/// let mut events = event_stream::<ChatChunk, _>(response.bytes_stream());
+/// while let Some(chunk) = events.next().await {
+///     print!("{}", chunk?.choices[0].delta.content.clone().unwrap_or_default());
+/// }
+/// ```
+pub fn event_stream<T, S>(bytes: S) -> EventStream<T>
+    where T: DeserializeOwned + Send + 'static,
+          S: Stream<Item=reqwest::Result<Bytes>> + Send + 'static
+{
+    events(bytes, None)
+}
+
+fn events<T, S>(bytes: S, reservation: Option<Reservation>) -> EventStream<T>
+    where T: DeserializeOwned + Send + 'static,
+          S: Stream<Item=reqwest::Result<Bytes>> + Send + 'static
+{
+    let state = SseState {
+        bytes: Box::pin(bytes),
+        buffer: BytesMut::new(),
+        closed: false,
+        finished: false,
+        reservation
+    };
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if state.finished {
+                return None
+            }
+            if let Some(data) = state.next_data() {
+                if data.trim() == DONE {
+                    return None
+                }
+                state.settle_usage(&data);
+                return Some((parse_event::<T>(data), state))
+            }
+            if state.closed {
+                return None
+            }
+            match state.bytes.next().await {
+                Some(Ok(chunk)) => state.buffer.extend_from_slice(&chunk),
+                Some(Err(err)) => {
+                    state.finished = true;
+                    return Some((Err(OpenAiError::Transport(err)), state))
+                }
+                //flush last event, if server closed connection without trailing blank line
+                None => {
+                    state.closed = true;
+                    state.buffer.extend_from_slice(b"\n\n");
+                }
+            }
+        }
+    }))
+}
@@ -0,0 +1,243 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::OnceLock;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use fancy_regex::Regex;
+use crate::chat::{ContentPart, Content, Message};
+
+///Byte pair encodings used by OpenAI models. Rank files are bundled with the crate,
+///so tokenizer works offline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    ///gpt-4, gpt-3.5-turbo and embedding models
+    Cl100kBase,
+    ///gpt-4o, gpt-4.1, o-series and newer models
+    O200kBase
+}
+
+impl Encoding {
+
+    ///encoding used by model, models not known to use cl100k_base get o200k_base
+    pub fn for_model(model: &str) -> Encoding {
+        const CL100K: [&str; 5] = ["gpt-3.5", "gpt-35", "text-embedding", "davinci-002", "babbage-002"];
+        let gpt_4 = model.starts_with("gpt-4") && !model.starts_with("gpt-4o") && !model.starts_with("gpt-4.");
+        if gpt_4 || CL100K.iter().any(|prefix| model.starts_with(prefix)) {
+            Encoding::Cl100kBase
+        } else {
+            Encoding::O200kBase
+        }
+    }
+
+    fn ranks(&self) -> &'static str {
+        match self {
+            Encoding::Cl100kBase => include_str!("../assets/cl100k_base.tiktoken"),
+            Encoding::O200kBase => include_str!("../assets/o200k_base.tiktoken")
+        }
+    }
+
+    fn pattern(&self) -> String {
+        match self {
+            Encoding::Cl100kBase => r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+".to_string(),
+            Encoding::O200kBase => [
+                r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+                r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+                r"\p{N}{1,3}",
+                r" ?[^\s\p{L}\p{N}]+[\r\n/]*",
+                r"\s*[\r\n]+",
+                r"\s+(?!\S)",
+                r"\s+"
+            ].join("|")
+        }
+    }
+}
+
+///Tokenizer for one of the encodings. Building it parses rank file,
+///so use `Tokenizer::get` or `Tokenizer::for_model`, that build each encoding once and share it.
+/// # Usage example
+/// ```
+/// use openai_req::tokenizer::Tokenizer;
+///
+/// let tokenizer = Tokenizer::for_model("gpt-4o");
+/// let tokens = tokenizer.encode("hello world");
+/// assert_eq!(tokenizer.decode(&tokens), "hello world");
+/// ```
+#[derive(Debug)]
+pub struct Tokenizer {
+    encoding: Encoding,
+    encoder: HashMap<Vec<u8>, u32>,
+    decoder: HashMap<u32, Vec<u8>>,
+    pattern: Regex
+}
+
+impl Tokenizer {
+
+    fn new(encoding: Encoding) -> Self {
+        let mut encoder = HashMap::new();
+        let mut decoder = HashMap::new();
+        for line in encoding.ranks().lines() {
+            let (token, rank) = match line.split_once(' ') {
+                Some(entry) => entry,
+                None => continue
+            };
+            //bundled files are known to be valid
+            let token = STANDARD.decode(token).expect("invalid token in rank file");
+            let rank = rank.parse::<u32>().expect("invalid rank in rank file");
+            decoder.insert(rank, token.clone());
+            encoder.insert(token, rank);
+        }
+        let pattern = Regex::new(&encoding.pattern()).expect("invalid split pattern");
+        Tokenizer { encoding, encoder, decoder, pattern }
+    }
+
+    ///shared tokenizer for the encoding, built on first use
+    pub fn get(encoding: Encoding) -> &'static Tokenizer {
+        static CL100K: OnceLock<Tokenizer> = OnceLock::new();
+        static O200K: OnceLock<Tokenizer> = OnceLock::new();
+        match encoding {
+            Encoding::Cl100kBase => CL100K.get_or_init(|| Tokenizer::new(encoding)),
+            Encoding::O200kBase => O200K.get_or_init(|| Tokenizer::new(encoding))
+        }
+    }
+
+    ///shared tokenizer for the encoding used by the model
+    pub fn for_model(model: &str) -> &'static Tokenizer {
+        Tokenizer::get(Encoding::for_model(model))
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    ///tokens of the text. Special tokens, like `<|endoftext|>`, are encoded as plain text
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        for piece in self.pattern.find_iter(text) {
+            match piece {
+                Ok(piece) => {
+                    self.encode_piece(piece.as_str(), &mut tokens);
+                    position = piece.end();
+                }
+                //lookahead runs on backtracking VM, that can give up on long input.
+                //Rest of the text is then encoded without splitting it into pieces
+                Err(_) => {
+                    self.encode_piece(&text[position..], &mut tokens);
+                    break
+                }
+            }
+        }
+        tokens
+    }
+
+    fn encode_piece(&self, piece: &str, tokens: &mut Vec<u32>) {
+        match self.encoder.get(piece.as_bytes()) {
+            Some(rank) => tokens.push(*rank),
+            None => tokens.extend(self.byte_pair_encode(piece.as_bytes()))
+        }
+    }
+
+    ///number of tokens in the text
+    pub fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+
+    ///text of the tokens, unknown tokens are skipped and invalid UTF-8 is replaced
+    pub fn decode(&self, tokens: &[u32]) -> String {
+        String::from_utf8_lossy(&self.decode_bytes(tokens)).into_owned()
+    }
+
+    ///raw bytes of the tokens, single token may hold only part of UTF-8 character
+    pub fn decode_bytes(&self, tokens: &[u32]) -> Vec<u8> {
+        tokens.iter()
+            .filter_map(|token| self.decoder.get(token))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    ///Merges adjacent parts of the piece with the lowest rank (leftmost first), until no pair can be merged.
+    ///Pairs are kept in a heap and parts in a linked list, so long pieces, like runs of whitespace
+    ///or encoded data, take `n log n` instead of quadratic time
+    fn byte_pair_encode(&self, piece: &[u8]) -> Vec<u32> {
+        let rank_of = |bytes: &[u8]| self.encoder.get(bytes).copied().unwrap_or(u32::MAX);
+        let len = piece.len();
+        //parts are identified by their start, `next` is start of the following part
+        let mut next: Vec<usize> = (1..=len).collect();
+        let mut previous: Vec<usize> = (0..len).map(|start| start.saturating_sub(1)).collect();
+        let pair_rank = |next: &[usize], start: usize| match next[start] {
+            middle if middle < len => rank_of(&piece[start..next[middle]]),
+            _ => u32::MAX
+        };
+        //rank of the pair starting at each part, parts merged into previous one get `u32::MAX`
+        let mut ranks: Vec<u32> = (0..len).map(|start| pair_rank(&next, start)).collect();
+        let mut heap: BinaryHeap<Reverse<(u32, usize)>> = ranks.iter()
+            .enumerate()
+            .filter(|(_, rank)| **rank != u32::MAX)
+            .map(|(start, rank)| Reverse((*rank, start)))
+            .collect();
+        while let Some(Reverse((rank, start))) = heap.pop() {
+            //pair was changed by earlier merge, different bytes always have different rank
+            if ranks[start] != rank {
+                continue
+            }
+            let merged = next[start];
+            next[start] = next[merged];
+            if next[start] < len {
+                previous[next[start]] = start;
+            }
+            ranks[merged] = u32::MAX;
+            let mut update = |start: usize, ranks: &mut Vec<u32>| {
+                ranks[start] = pair_rank(&next, start);
+                if ranks[start] != u32::MAX {
+                    heap.push(Reverse((ranks[start], start)));
+                }
+            };
+            update(start, &mut ranks);
+            if start > 0 {
+                update(previous[start], &mut ranks);
+            }
+        }
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while start < len {
+            tokens.push(rank_of(&piece[start..next[start]]));
+            start = next[start];
+        }
+        tokens
+    }
+}
+
+///Prompt tokens of chat messages, as counted by API: content, role and name of every message,
+///plus fixed per-message overhead and tokens that prime the answer.
+///Image and audio parts are not counted, their cost depends on size and detail level.
+pub fn count_chat_tokens(messages: &[Message], model: &str) -> u64 {
+    const TOKENS_PER_MESSAGE: u64 = 3;
+    const TOKENS_PER_NAME: u64 = 1;
+    const REPLY_PRIMING: u64 = 3;
+    let tokenizer = Tokenizer::for_model(model);
+    let count = |text: &str| tokenizer.count(text) as u64;
+    let mut total = REPLY_PRIMING;
+    for message in messages {
+        total += TOKENS_PER_MESSAGE;
+        if let Ok(serde_json::Value::String(role)) = serde_json::to_value(&message.role) {
+            total += count(&role);
+        }
+        total += match &message.content {
+            Content::Text(text) => count(text),
+            Content::Parts(parts) => parts.iter()
+                .map(|part| match part {
+                    ContentPart::Text { text } => count(text),
+                    _ => 0
+                })
+                .sum()
+        };
+        if let Some(name) = &message.name {
+            total += TOKENS_PER_NAME + count(name);
+        }
+        for call in message.tool_calls.iter().flatten() {
+            total += count(&call.function.name) + count(&call.function.arguments);
+        }
+    }
+    total
+}
@@ -0,0 +1,125 @@
+//! Optional request instrumentation, enabled with `tracing` cargo feature.
+//! Every request gets `openai_request` span with endpoint, model, status, latency and token usage.
+//! Request and response bodies are only logged at trace level, with API key redacted.
+//! Without the feature all of this compiles to nothing.
+
+use std::time::Duration;
+use reqwest::{Request, Response, StatusCode};
+
+#[cfg(feature = "tracing")]
+use crate::ReportedUsage;
+
+///span of single logical request, shared by all retry attempts
+#[derive(Clone, Debug)]
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    key: String
+}
+
+#[cfg(feature = "tracing")]
+impl RequestSpan {
+
+    pub(crate) fn new(request: &Request, key: &str) -> Self {
+        let body = request.body().map(|body| body.as_bytes());
+        let model = body.flatten()
+            .and_then(|body| serde_json::from_slice::<serde_json::Value>(body).ok())
+            .and_then(|body| body.get("model").and_then(|model| model.as_str()).map(str::to_string));
+        let span = tracing::info_span!(
+            "openai_request",
+            method = %request.method(),
+            endpoint = %request.url().path(),
+            model = tracing::field::Empty,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+            total_tokens = tracing::field::Empty
+        );
+        if let Some(model) = model {
+            span.record("model", model.as_str());
+        }
+        let span = RequestSpan { span, key: key.to_string() };
+        if tracing::enabled!(tracing::Level::TRACE) {
+            let headers = request.headers().iter()
+                .map(|(name, value)| {
+                    let value = match name.as_str() {
+                        "authorization" | "api-key" => "[REDACTED]".to_string(),
+                        _ => span.redact(value.to_str().unwrap_or("<binary>"))
+                    };
+                    format!("{}: {}", name, value)
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+            let body = match body {
+                Some(Some(body)) => span.redact(&String::from_utf8_lossy(body)),
+                Some(None) => "<stream>".to_string(),
+                None => "<empty>".to_string()
+            };
+            span.span.in_scope(|| tracing::trace!(headers = %headers, body = %body, "request"));
+        }
+        span
+    }
+
+    fn redact(&self, text: &str) -> String {
+        if self.key.is_empty() {
+            text.to_string()
+        } else {
+            text.replace(&self.key, "[REDACTED]")
+        }
+    }
+
+    pub(crate) fn record_attempt(&self, attempt: u32, status: Option<StatusCode>, latency: Duration) {
+        self.span.record("attempts", attempt + 1);
+        self.span.record("latency_ms", latency.as_millis() as u64);
+        if let Some(status) = status {
+            self.span.record("status", status.as_u16());
+        }
+        self.span.in_scope(|| tracing::debug!(attempt = attempt + 1, status = status.map(|s| s.as_u16()), "response received"));
+    }
+
+    pub(crate) fn record_body(&self, body: &str) {
+        if let Some(usage) = serde_json::from_str::<ReportedUsage>(body).ok().and_then(|r| r.usage) {
+            self.span.record("prompt_tokens", usage.prompt_tokens);
+            if let Some(completion_tokens) = usage.completion_tokens {
+                self.span.record("completion_tokens", completion_tokens);
+            }
+            self.span.record("total_tokens", usage.total_tokens);
+        }
+        self.span.in_scope(|| tracing::trace!(body = %self.redact(body), "response"));
+    }
+
+    pub(crate) fn record_error(&self, error: &dyn std::fmt::Display) {
+        self.span.in_scope(|| tracing::warn!(error = %error, "request failed"));
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl RequestSpan {
+
+    pub(crate) fn new(_request: &Request, _key: &str) -> Self {
+        RequestSpan {}
+    }
+
+    pub(crate) fn record_attempt(&self, _attempt: u32, _status: Option<StatusCode>, _latency: Duration) {}
+
+    pub(crate) fn record_body(&self, _body: &str) {}
+
+    pub(crate) fn record_error(&self, _error: &dyn std::fmt::Display) {}
+}
+
+impl RequestSpan {
+
+    ///span travels with the response, so body processing can be recorded in it
+    pub(crate) fn attach(self, response: &mut Response) {
+        if cfg!(feature = "tracing") {
+            response.extensions_mut().insert(self);
+        }
+    }
+
+    pub(crate) fn take(response: &mut Response) -> Option<RequestSpan> {
+        response.extensions_mut().remove::<RequestSpan>()
+    }
+}
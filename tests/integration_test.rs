@@ -6,16 +6,28 @@ use file_diff::diff;
 use serde::Deserialize;
 use tokio::try_join;
 use openai_req::*;
-use openai_req::chat::{ChatRequest, Message, Role};
+use openai_req::chat::{ChatChunk, ChatRequest, ChatSuccess, ContentPart, ImageDetail, Message, ReasoningEffort, ResponseFormat as ChatResponseFormat, Role, ServiceTier, ToolCall, ToolChoice};
+use openai_req::chat::tools::ToolRegistry;
+use openai_req::chat::conversation::{context_window_for, Conversation, Overflow};
 use openai_req::edit::EditRequest;
 use openai_req::audio::{Iso639_1, ResponseFormat, TranscriptionRequest, TranslationRequest};
-use openai_req::completion::CompletionRequest;
-use openai_req::embeddings::EmbeddingRequest;
+use openai_req::completion::{CompletionRequest, CompletionSuccess};
+use openai_req::embeddings::{EmbeddingRequest, EmbeddingResponse, EncodingFormat};
+use openai_req::embeddings::batch::EmbeddingBatcher;
+use openai_req::embeddings::index::{HnswParams, Metric, VectorIndex};
+use openai_req::embeddings::splitter::TextSplitter;
 use openai_req::files::{FileDeleteRequest, FileDownloadRequest, FileInfoRequest, FileListResponse, FileUploadRequest};
 use openai_req::fine_tunes::{FineTuneCreateRequest, FineTuneEventsGetRequest, FineTuneListResponse};
 use openai_req::image::{ImageEditRequest, ImageRequest, ImageSize, ImageVariationRequest};
 use openai_req::model::{ModelDeleteRequest, ModelListResponse};
 use openai_req::moderations::ModerationRequest;
+use openai_req::meta::ResponseMeta;
+use openai_req::limiter::{estimate_tokens, ModelLimits, RateLimiter};
+use openai_req::logit_bias::LogitBias;
+use openai_req::logprobs::TokenProbabilities;
+use openai_req::auth::{AuthProvider, Credential, FileKey};
+use openai_req::azure::AzureConfig;
+use openai_req::retry::RetryPolicy;
 
 
 #[derive(Deserialize)]
@@ -31,7 +43,7 @@ fn get_client() -> OpenAiClient{
         toml::from_str(&key_config)
             .expect("can't parse config file");
 
-    return  OpenAiClient::new(&openai.key);
+    OpenAiClient::new(&openai.key)
 }
 
 #[tokio::test]
@@ -39,7 +51,8 @@ async fn chat() -> Result<(),anyhow::Error> {
    let client = get_client();
    let messages  = vec!(Message{
      role: Role::User,
-     content: "hello!".to_string(),
+     content: "hello!".into(),
+     ..Default::default()
    });
    let mut chat_request = ChatRequest::new(messages);
    let response = chat_request.run(&client).await?;
@@ -47,14 +60,931 @@ async fn chat() -> Result<(),anyhow::Error> {
    chat_request.push_message(response.choices[0].message.clone());
    chat_request.push_message(Message {
        role: Role::User,
-       content: "how are you?".to_string()
+       content: "how are you?".into(),
+       ..Default::default()
    });
-   dbg!(&reqwest);
+   dbg!(&chat_request);
    let response = chat_request.run(&client).await?;
    dbg!(response);
    Ok(())
 }
 
+#[tokio::test]
+async fn chat_stream() -> Result<(),anyhow::Error> {
+    let client = get_client();
+    let messages  = vec!(Message{
+        role: Role::User,
+        content: "hello!".into(),
+        ..Default::default()
+    });
+    let chat_request = ChatRequest::new(messages);
+    let stream = chat_request.run_stream(&client).await?;
+    let response = ChatSuccess::collect_stream(stream).await?;
+    dbg!(&response);
+    assert!(!response.choices[0].message.content.is_empty());
+    Ok(())
+}
+
+fn sse_body(parts: &[&'static str]) -> impl futures_util::Stream<Item=reqwest::Result<bytes::Bytes>> {
+    futures_util::stream::iter(parts.iter().map(|part| Ok(bytes::Bytes::from_static(part.as_bytes()))).collect::<Vec<_>>())
+}
+
+#[tokio::test]
+async fn sse_parsing() -> Result<(),anyhow::Error> {
+    use futures_util::StreamExt;
+    //events split across chunks, CRLF separators, comments and multi-line data
+    let body = sse_body(&[
+        ": keep-alive\r\n\r\nda", "ta: {\"a\":", "1}\r\n\r", "\ndata: {\"a\":\n", "data: 2}\n\n",
+        "data: [DONE]\n\n", "data: {\"a\":3}\n\n"
+    ]);
+    let events = sse::event_stream::<serde_json::Value, _>(body).collect::<Vec<_>>().await;
+    let values = events.into_iter().collect::<openai_req::Result<Vec<_>>>()?;
+    assert_eq!(values, vec![serde_json::json!({"a": 1}), serde_json::json!({"a": 2})]);
+    //last event is delivered even without trailing blank line
+    let body = sse_body(&["data: {\"a\":1}\n\ndata: {\"a\":2}"]);
+    assert_eq!(sse::event_stream::<serde_json::Value, _>(body).count().await, 2);
+    //error reported in the middle of the stream
+    let body = sse_body(&[
+        "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"choices\":[]}\n\n",
+        "data: {\"error\":{\"message\":\"overloaded\",\"type\":\"server_error\",\"param\":null,\"code\":null}}\n\n"
+    ]);
+    let mut events = sse::event_stream::<ChatChunk, _>(body);
+    assert_eq!(events.next().await.unwrap()?.id, "1");
+    assert!(matches!(events.next().await, Some(Err(OpenAiError::Stream{ details })) if details.message == "overloaded"));
+    assert!(matches!(sse::event_stream::<ChatChunk, _>(sse_body(&["data: {}\n\n"])).next().await, Some(Err(OpenAiError::Deserialization{..}))));
+    Ok(())
+}
+
+#[test]
+fn chat_chunks_fold() -> Result<(),anyhow::Error> {
+    let chunks: Vec<ChatChunk> = vec![
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}"#)?,
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":1,"delta":{"content":"Bye"},"finish_reason":null}]}"#)?,
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"content":"Hel"},"finish_reason":null}]}"#)?,
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":"stop"}]}"#)?,
+    ];
+    let response: ChatSuccess = chunks.into_iter().collect();
+    assert_eq!(response.choices.len(), 2);
+    assert_eq!(response.choices[0].message.content, "Hello");
+    assert_eq!(response.choices[0].finish_reason, "stop");
+    assert_eq!(response.choices[1].message.content, "Bye");
+    Ok(())
+}
+
+
+#[test]
+fn tool_calls_parsing() -> Result<(),anyhow::Error> {
+    #[derive(Deserialize)]
+    struct WeatherArgs { city: String }
+    let response: ChatSuccess = serde_json::from_str(r#"{"id":"1","object":"chat.completion","created":1,
+        "choices":[{"index":0,"finish_reason":"tool_calls","message":{"role":"assistant","content":null,
+        "tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":\"Paris\"}"}}]}}],
+        "usage":{"prompt_tokens":1,"completion_tokens":1,"total_tokens":2}}"#)?;
+    let message = &response.choices[0].message;
+    assert!(message.content.is_empty());
+    let call = &message.tool_calls.as_ref().unwrap()[0];
+    let args: WeatherArgs = call.function.parse_arguments()?;
+    assert_eq!(args.city, "Paris");
+    let request = ChatRequest::new(vec![message.clone(), Message::tool(&call.id, "sunny")])
+        .tool_choice(ToolChoice::Function("get_weather".to_string()));
+    let json = serde_json::to_value(&request)?;
+    assert!(json["messages"][0].get("content").is_none());
+    //empty text is only dropped from messages that call tools
+    let json = serde_json::to_value(Message::new(Role::User, ""))?;
+    assert_eq!(json, serde_json::json!({"role": "user", "content": ""}));
+    let json = serde_json::to_value(&request)?;
+    assert_eq!(json["messages"][1]["role"], "tool");
+    assert_eq!(json["messages"][1]["tool_call_id"], "call_1");
+    assert_eq!(json["tool_choice"]["function"]["name"], "get_weather");
+    let chunks: Vec<ChatChunk> = vec![
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"role":"assistant","content":null,"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":""}}]},"finish_reason":null}]}"#)?,
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]},"finish_reason":null}]}"#)?,
+        serde_json::from_str(r#"{"id":"1","object":"chat.completion.chunk","created":1,"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]},"finish_reason":"tool_calls"}]}"#)?,
+    ];
+    let streamed: ChatSuccess = chunks.into_iter().collect();
+    let call = &streamed.choices[0].message.tool_calls.as_ref().unwrap()[0];
+    assert_eq!(call.id, "call_1");
+    assert_eq!(call.function.parse_arguments::<WeatherArgs>()?.city, "Paris");
+    Ok(())
+}
+
+#[tokio::test]
+async fn tool_registry_call() -> Result<(),anyhow::Error> {
+    let registry = ToolRegistry::new()
+        .register("add", "adds two numbers", serde_json::json!({"type": "object"}), |args: serde_json::Value| async move {
+            match (args["a"].as_i64(), args["b"].as_i64()) {
+                (Some(a), Some(b)) => Ok((a + b).to_string()),
+                _ => Err("a and b must be integers")
+            }
+        });
+    let call = |arguments: &str| serde_json::from_value::<ToolCall>(serde_json::json!({
+        "id": "call_1", "type": "function", "function": {"name": "add", "arguments": arguments}
+    }));
+    assert_eq!(registry.call(&call(r#"{"a":2,"b":3}"#)?).await.content, "5");
+    assert_eq!(registry.call(&call(r#"{"a":2}"#)?).await.content, "error: a and b must be integers");
+    assert!(registry.call(&call("{not json")?).await.content.text().starts_with("error:"));
+    assert_eq!(registry.definitions()[0].function.name, "add");
+    Ok(())
+}
+
+#[tokio::test]
+async fn run_with_tools() -> Result<(),anyhow::Error> {
+    let registry = ToolRegistry::new()
+        .register("add", "adds two numbers", serde_json::json!({"type": "object"}), |args: serde_json::Value| async move {
+            Ok::<String, String>((args["a"].as_i64().unwrap_or(0) + args["b"].as_i64().unwrap_or(0)).to_string())
+        });
+    let calls = chat_response(serde_json::json!({"role": "assistant", "content": null, "tool_calls": [
+        {"id": "call_1", "type": "function", "function": {"name": "add", "arguments": r#"{"a":2,"b":3}"#}},
+        {"id": "call_2", "type": "function", "function": {"name": "add", "arguments": r#"{"a":1,"b":1}"#}}
+    ]}), "tool_calls");
+    let answer = chat_response(serde_json::json!({"role": "assistant", "content": "5 and 2"}), "stop");
+    let (url, server) = mock_server(vec![calls.clone(), answer]).await;
+    let client = OpenAiClient::with_url("", &url);
+    let request = ChatRequest::new(vec![Message::new(Role::User, "add 2 and 3, then 1 and 1")]);
+    let run = request.run_with_tools(&client, &registry).await?;
+    assert_eq!(run.response.choices[0].message.content, "5 and 2");
+    assert_eq!(run.transcript.len(), 5);
+    assert_eq!((&run.transcript[2].content, run.transcript[2].tool_call_id.as_deref()), (&"5".into(), Some("call_1")));
+    assert_eq!((&run.transcript[3].content, run.transcript[3].tool_call_id.as_deref()), (&"2".into(), Some("call_2")));
+    let requests = server.await?;
+    let bodies = requests.iter()
+        .map(|request| serde_json::from_str::<serde_json::Value>(request.split("\r\n\r\n").nth(1).unwrap()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(bodies[0]["tools"][0]["function"]["name"], "add");
+    assert_eq!(bodies[0]["messages"].as_array().unwrap().len(), 1);
+    //results are sent back after the call that requested them
+    let sent = bodies[1]["messages"].as_array().unwrap();
+    assert_eq!(sent[1]["tool_calls"][1]["id"], "call_2");
+    assert_eq!((&sent[2]["role"], &sent[2]["tool_call_id"], &sent[2]["content"]), (&"tool".into(), &"call_1".into(), &"5".into()));
+    //model that keeps calling tools is stopped after max_iterations requests
+    let (url, server) = mock_server(vec![calls; 2]).await;
+    let client = OpenAiClient::with_url("", &url);
+    let result = request.run_with_tools(&client, &registry.max_iterations(2)).await;
+    assert!(matches!(result, Err(OpenAiError::ToolIterations(2))));
+    assert_eq!(server.await?.len(), 2);
+    Ok(())
+}
+
+#[cfg(feature = "schemars")]
+#[tokio::test]
+async fn typed_tool_schema() -> Result<(),anyhow::Error> {
+    #[derive(Deserialize, schemars::JsonSchema)]
+    struct WeatherArgs {
+        ///name of the city
+        city: String,
+        days: Option<u8>
+    }
+    let registry = ToolRegistry::new()
+        .register_typed("get_weather", "weather forecast", |args: WeatherArgs| async move {
+            Ok::<String, String>(format!("sunny in {} for {} days", args.city, args.days.unwrap_or(1)))
+        });
+    let schema = &registry.definitions()[0].function.parameters;
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["required"], serde_json::json!(["city"]));
+    assert_eq!(schema["properties"]["city"]["description"], "name of the city");
+    let call = |arguments: &str| serde_json::from_value::<ToolCall>(serde_json::json!({
+        "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": arguments}
+    }));
+    assert_eq!(registry.call(&call(r#"{"city":"Paris"}"#)?).await.content, "sunny in Paris for 1 days");
+    assert!(registry.call(&call(r#"{"town":"Paris"}"#)?).await.content.text().starts_with("error: arguments do not match schema"));
+    Ok(())
+}
+
+#[test]
+fn structured_content_parsing() -> Result<(),anyhow::Error> {
+    #[derive(Deserialize)]
+    struct Answer { value: i32 }
+    let response = |message: &str, finish_reason: &str| serde_json::from_str::<ChatSuccess>(&format!(
+        r#"{{"id":"1","object":"chat.completion","created":1,"choices":[{{"index":0,"finish_reason":"{}","message":{}}}],
+        "usage":{{"prompt_tokens":1,"completion_tokens":1,"total_tokens":2}}}}"#, finish_reason, message));
+    let answer: Answer = response(r#"{"role":"assistant","content":"{\"value\":42}"}"#, "stop")?.parse_content()?;
+    assert_eq!(answer.value, 42);
+    let refused = response(r#"{"role":"assistant","content":null,"refusal":"can not help"}"#, "stop")?.parse_content::<Answer>();
+    assert!(matches!(refused, Err(OpenAiError::Refusal(refusal)) if refusal == "can not help"));
+    let truncated = response(r#"{"role":"assistant","content":"{\"val"}"#, "length")?.parse_content::<Answer>();
+    assert!(matches!(truncated, Err(OpenAiError::Truncated(partial)) if partial == "{\"val"));
+    let empty = ChatSuccess::default().parse_content::<Answer>();
+    assert!(matches!(empty, Err(OpenAiError::NoChoices)));
+    let request = ChatRequest::new(vec![]).response_format(ChatResponseFormat::JsonObject);
+    assert_eq!(serde_json::to_value(&request)?["response_format"], serde_json::json!({"type": "json_object"}));
+    Ok(())
+}
+
+#[cfg(feature = "schemars")]
+#[test]
+fn strict_response_format() -> Result<(),anyhow::Error> {
+    #[derive(Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Person { name: String, age: Option<u8> }
+    let format = serde_json::to_value(ChatResponseFormat::for_type::<Person>())?;
+    assert_eq!(format["type"], "json_schema");
+    assert_eq!(format["json_schema"]["name"], "Person");
+    assert_eq!(format["json_schema"]["strict"], true);
+    let schema = &format["json_schema"]["schema"];
+    assert_eq!(schema["required"], serde_json::json!(["age", "name"]));
+    assert_eq!(schema["additionalProperties"], false);
+    assert!(schema["properties"]["age"].get("format").is_none());
+    //field names are not schema keywords
+    #[derive(Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Setting { value: Option<u32> }
+    #[derive(Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Settings { properties: Vec<Setting>, required: bool }
+    let format = serde_json::to_value(ChatResponseFormat::for_type::<Settings>())?;
+    let schema = &format["json_schema"]["schema"];
+    let fields = schema["properties"].as_object().unwrap().keys().collect::<Vec<_>>();
+    assert_eq!(fields, vec!["properties", "required"]);
+    assert_eq!(schema["required"], serde_json::json!(["properties", "required"]));
+    let definitions = schema.get("$defs").or(schema.get("definitions")).unwrap();
+    assert_eq!(definitions["Setting"]["required"], serde_json::json!(["value"]));
+    assert_eq!(definitions["Setting"]["additionalProperties"], false);
+    assert!(definitions["Setting"]["properties"]["value"].get("format").is_none());
+    Ok(())
+}
+
+#[test]
+fn multimodal_content() -> Result<(),anyhow::Error> {
+    let text = serde_json::to_value(Message::new(Role::User, "hello"))?;
+    assert_eq!(text, serde_json::json!({"role": "user", "content": "hello"}));
+    let png = bytes::Bytes::from_static(b"\x89PNG\r\n\x1a\n0000");
+    let message = Message::with_parts(Role::User, vec![
+        ContentPart::text("what is on the picture?"),
+        ContentPart::image_bytes(&png, Some(ImageDetail::Low))?
+    ]);
+    let json = serde_json::to_value(&message)?;
+    assert_eq!(json["content"][0], serde_json::json!({"type": "text", "text": "what is on the picture?"}));
+    assert_eq!(json["content"][1]["type"], "image_url");
+    assert_eq!(json["content"][1]["image_url"]["detail"], "low");
+    assert!(json["content"][1]["image_url"]["url"].as_str().unwrap().starts_with("data:image/png;base64,"));
+    let parsed: Message = serde_json::from_value(json)?;
+    assert_eq!(parsed.content.text(), "what is on the picture?");
+    let unsupported = ContentPart::image_bytes(&bytes::Bytes::from_static(b"not an image"), None);
+    assert!(matches!(unsupported, Err(OpenAiError::InvalidParameter{ param, .. }) if param == "image"));
+    let unsupported = ContentPart::audio_bytes(&bytes::Bytes::from_static(b"not audio"));
+    assert!(matches!(unsupported, Err(OpenAiError::InvalidParameter{ param, .. }) if param == "input_audio"));
+    Ok(())
+}
+
+///each message costs 10 tokens, so only 5 messages fit
+#[tokio::test]
+async fn conversation_trimming() -> Result<(),anyhow::Error> {
+    let client = OpenAiClient::new("");
+    let mut conversation = Conversation::new("gpt-4o")
+        .system("be brief")
+        .context_window(60)
+        .reserve(10)
+        .counter(|messages: &[Message]| messages.len() as u64 * 10);
+    conversation.push_pinned(Message::new(Role::User, "my name is Bob"));
+    conversation.push(Message::new(Role::User, "what is the weather?"));
+    let call: ToolCall = serde_json::from_value(serde_json::json!({
+        "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{}"}
+    }))?;
+    conversation.push(Message { role: Role::Assistant, tool_calls: Some(vec![call]), ..Default::default() });
+    conversation.push(Message::tool("call_1", "sunny"));
+    conversation.push(Message::new(Role::Assistant, "it is sunny"));
+    conversation.push(Message::new(Role::User, "thanks"));
+    conversation.fit(&client).await?;
+    let contents: Vec<String> = conversation.messages().iter().map(|m| m.content.text()).collect();
+    //tool call is dropped together with its result, pinned message stays
+    assert_eq!(contents, vec!["be brief", "my name is Bob", "it is sunny", "thanks"]);
+    assert_eq!(context_window_for("gpt-4o-mini"), 128_000);
+    Ok(())
+}
+
+///each message costs 10 tokens, so only 5 messages fit, summary included
+#[tokio::test]
+async fn conversation_summary() -> Result<(),anyhow::Error> {
+    let summary = |text: &str| chat_response(serde_json::json!({"role": "assistant", "content": text}), "stop");
+    let (url, server) = mock_server(vec![summary("first summary"), summary("second summary")]).await;
+    let client = OpenAiClient::with_url("", &url);
+    let mut conversation = Conversation::new("gpt-4o")
+        .system("be brief")
+        .context_window(60)
+        .reserve(10)
+        .overflow(Overflow::Summarize)
+        .counter(|messages: &[Message]| messages.len() as u64 * 10);
+    for text in ["one", "two", "three", "four", "five", "six"] {
+        conversation.push(Message::new(Role::User, text));
+    }
+    conversation.fit(&client).await?;
+    let contents: Vec<String> = conversation.messages().iter().map(|m| m.content.text()).collect();
+    assert_eq!(contents, vec!["be brief", "Summary of the earlier conversation: second summary", "four", "five", "six"]);
+    //turn pushed out by the first summary is summarized together with it
+    let requests = server.await?;
+    assert!(requests[0].contains("user: one\\nuser: two"));
+    assert!(requests[1].contains("first summary\\nuser: three"));
+    Ok(())
+}
+
+#[cfg(feature = "tokenizer")]
+#[test]
+fn tokenizer_encodings() {
+    use openai_req::tokenizer::{count_chat_tokens, Encoding, Tokenizer};
+    let cl100k = Tokenizer::for_model("gpt-4");
+    assert_eq!(cl100k.encoding(), Encoding::Cl100kBase);
+    assert_eq!(cl100k.encode("hello world"), vec![15339, 1917]);
+    let o200k = Tokenizer::for_model("gpt-4o-mini");
+    assert_eq!(o200k.encoding(), Encoding::O200kBase);
+    assert_eq!(o200k.encode("hello world"), vec![24912, 2375]);
+    let text = "Привет, мир! 你好世界 🚀 it's   spaced\n\n";
+    assert_eq!(o200k.decode(&o200k.encode(text)), text);
+    assert_eq!(cl100k.decode(&cl100k.encode(text)), text);
+    //3 per message, 1 for role, 2 for content, 3 to prime the answer
+    assert_eq!(count_chat_tokens(&[Message::new(Role::User, "hello world")], "gpt-4o"), 9);
+}
+
+#[cfg(feature = "tokenizer")]
+#[test]
+fn tokenizer_long_pieces() {
+    use openai_req::tokenizer::Tokenizer;
+    let tokenizer = Tokenizer::for_model("gpt-4o");
+    //split pattern gives up on whitespace this long, text is still encoded in full
+    let spaces = " ".repeat(1_000_000) + "tail";
+    let tokens = tokenizer.encode(&spaces);
+    assert_eq!(tokenizer.decode(&tokens), spaces);
+    let data = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB".repeat(5_000);
+    let tokens = tokenizer.encode(&data);
+    assert!(tokens.len() > 10_000);
+    assert_eq!(tokenizer.decode(&tokens), data);
+}
+
+#[test]
+fn logit_bias_builder() -> Result<(),anyhow::Error> {
+    let bias = LogitBias::new().token(15339, 250.0).token(1917, -3.5);
+    let json = serde_json::to_value(ChatRequest::new(vec![]).logit_bias(bias))?;
+    assert_eq!(json["logit_bias"], serde_json::json!({"15339": 100.0, "1917": -3.5}));
+    #[cfg(feature = "tokenizer")]
+    {
+        let banned: std::collections::HashMap<String, f32> = LogitBias::for_model("gpt-4").ban("hello").into();
+        //"hello" and " hello" are single, different tokens
+        assert_eq!(banned.len(), 2);
+        assert_eq!(banned.get("15339"), Some(&-100.0));
+    }
+    Ok(())
+}
+
+#[test]
+fn embedding_batches() {
+    let texts: Vec<String> = (0..5).map(|i| format!("text number {}", i)).collect();
+    let batches = EmbeddingBatcher::new("text-embedding-3-small").max_inputs(2).batches(texts.clone());
+    assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+    assert_eq!(batches.concat(), texts);
+    //text over token budget is sent alone
+    let batches = EmbeddingBatcher::new("text-embedding-3-small").max_tokens(1).batches(texts);
+    assert_eq!(batches.len(), 5);
+}
+
+#[test]
+fn embedding_formats() -> Result<(),anyhow::Error> {
+    let request = EmbeddingRequest::with_model("text-embedding-3-small".to_string(), "hello".into())
+        .encoding_format(EncodingFormat::Base64)
+        .dimensions(256);
+    assert!(JsonRequest::validate(&request).is_ok());
+    let json = serde_json::to_value(&request)?;
+    assert_eq!(json["encoding_format"], "base64");
+    assert_eq!(json["dimensions"], 256);
+    let request = EmbeddingRequest::new("hello".into()).dimensions(256);
+    assert!(matches!(JsonRequest::validate(&request), Err(OpenAiError::InvalidParameter{..})));
+    let response: EmbeddingResponse = serde_json::from_str(r#"{"object":"list","model":"text-embedding-3-small",
+        "data":[{"object":"embedding","index":0,"embedding":"AAAAPwAAoL8="},{"object":"embedding","index":1,"embedding":[0.5,-1.25]}],
+        "usage":{"prompt_tokens":2,"total_tokens":2}}"#)?;
+    assert_eq!(response.data[0].embedding, vec![0.5f32, -1.25]);
+    assert_eq!(response.data[0].embedding, response.data[1].embedding);
+    Ok(())
+}
+
+#[test]
+fn vector_index_search() -> Result<(),anyhow::Error> {
+    let mut index = VectorIndex::new(2, Metric::Euclidean);
+    index.insert("a", vec![0.0, 0.0], "first")?;
+    index.insert("b", vec![3.0, 4.0], "second")?;
+    let hits = index.search(&[3.0, 3.0], 2)?;
+    assert_eq!((hits[0].id, hits[0].score, *hits[0].metadata), ("b", 1.0, "second"));
+    assert!(matches!(index.insert("c", vec![1.0], ""), Err(OpenAiError::DimensionMismatch{ expected: 2, actual: 1 })));
+    //same id replaces vector
+    index.insert("b", vec![10.0, 10.0], "moved")?;
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.search(&[3.0, 3.0], 1)?[0].id, "a");
+    let mut index = VectorIndex::new(2, Metric::Dot).normalize(true);
+    index.insert("x", vec![10.0, 0.0], ())?;
+    assert_eq!(index.search(&[0.0, 3.0], 1)?[0].score, 0.0);
+    assert_eq!(index.get("x").unwrap().0, &[1.0, 0.0]);
+    //approximate search finds stored vectors
+    let mut seed = 7u64;
+    let mut random = move || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+    };
+    let vectors: Vec<Vec<f32>> = (0..300).map(|_| (0..8).map(|_| random()).collect()).collect();
+    let mut index = VectorIndex::new(8, Metric::Cosine).hnsw(HnswParams::default().m(8));
+    for (i, vector) in vectors.iter().enumerate() {
+        index.insert(&i.to_string(), vector.clone(), i)?;
+    }
+    for (i, vector) in vectors.iter().enumerate().step_by(10) {
+        let hits = index.search(vector, 5)?;
+        assert_eq!(*hits[0].metadata, i);
+        assert_eq!(hits.len(), 5);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+    let response: EmbeddingResponse = serde_json::from_str(r#"{"object":"list","model":"m",
+        "data":[{"object":"embedding","index":1,"embedding":[0.0,1.0]},{"object":"embedding","index":0,"embedding":[1.0,0.0]}],
+        "usage":{"prompt_tokens":2,"total_tokens":2}}"#)?;
+    let mut index = VectorIndex::new(2, Metric::Cosine);
+    //entries for a different batch are rejected before anything is inserted
+    let mismatched = index.insert_response(response.clone(), vec![("first".to_string(), 0)]);
+    assert!(matches!(mismatched, Err(OpenAiError::InvalidParameter{ param, .. }) if param == "entries"));
+    assert!(index.is_empty() && index.embedding_model().is_none());
+    index.insert_response(response, vec![("first".to_string(), 0), ("second".to_string(), 1)])?;
+    assert_eq!(index.get("first").unwrap().0, &[1.0, 0.0]);
+    Ok(())
+}
+
+#[test]
+fn vector_index_replace() -> Result<(),anyhow::Error> {
+    let mut seed = 7u64;
+    let mut random = move || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+    };
+    let params = HnswParams::default().m(8).ef_search(10);
+    let mut index = VectorIndex::new(16, Metric::Cosine).hnsw(params);
+    let mut vectors = Vec::new();
+    //every vector is replaced three times after the first insert
+    for _ in 0..4 {
+        vectors = (0..1000).map(|_| (0..16).map(|_| random()).collect::<Vec<f32>>()).collect::<Vec<_>>();
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(&i.to_string(), vector.clone(), i)?;
+        }
+    }
+    let mut fresh = VectorIndex::new(16, Metric::Cosine).hnsw(params);
+    for (i, vector) in vectors.iter().enumerate() {
+        fresh.insert(&i.to_string(), vector.clone(), i)?;
+    }
+    let queries = (0..100).map(|_| (0..16).map(|_| random()).collect::<Vec<f32>>()).collect::<Vec<_>>();
+    let recall = |index: &VectorIndex<usize>| queries.iter().map(|query| {
+        let exact = index.search_exact(query, 10).unwrap().into_iter().map(|hit| hit.id).collect::<Vec<_>>();
+        index.search(query, 10).unwrap().iter().filter(|hit| exact.contains(&hit.id)).count()
+    }).sum::<usize>();
+    //links to old positions of replaced vectors do not make search worse than in a fresh index
+    let (replaced, fresh) = (recall(&index), recall(&fresh));
+    assert!(replaced * 100 >= fresh * 95, "recall {} of fresh index {}", replaced, fresh);
+    Ok(())
+}
+
+#[test]
+fn vector_index_persistence() -> Result<(),anyhow::Error> {
+    let path = std::env::temp_dir().join("openai_req_index_test.idx");
+    let mut index = VectorIndex::new(3, Metric::Dot).model("text-embedding-3-small");
+    index.insert("a", vec![1.0, 0.0, 0.0], serde_json::json!({"page": 1}))?;
+    index.insert("b", vec![0.0, 1.0, 0.5], serde_json::json!({"page": 2}))?;
+    index.save(&path)?;
+    let mut loaded: VectorIndex = VectorIndex::open(&path)?;
+    assert_eq!(loaded.embedding_model(), Some("text-embedding-3-small"));
+    assert_eq!((loaded.len(), loaded.dimensions(), loaded.metric()), (2, 3, Metric::Dot));
+    let hits = loaded.search(&[0.0, 1.0, 1.0], 1)?;
+    assert_eq!((hits[0].id, hits[0].score, &hits[0].metadata["page"]), ("b", 1.5, &serde_json::json!(2)));
+    //changes are made on a copy, file is not touched
+    loaded.insert("a", vec![0.0, 0.0, 9.0], serde_json::json!({"page": 3}))?;
+    assert_eq!(VectorIndex::<serde_json::Value>::open(&path)?.get("a").unwrap().0, &[1.0, 0.0, 0.0]);
+    let request = EmbeddingRequest::with_model("text-embedding-3-small".to_string(), "".into()).dimensions(3);
+    assert!(VectorIndex::<serde_json::Value>::load(&path, &request).is_ok());
+    let request = EmbeddingRequest::with_model("text-embedding-3-small".to_string(), "".into());
+    assert!(matches!(VectorIndex::<serde_json::Value>::load(&path, &request), Err(OpenAiError::DimensionMismatch{ expected: 1536, actual: 3 })));
+    let request = EmbeddingRequest::with_model("text-embedding-3-large".to_string(), "".into()).dimensions(3);
+    assert!(matches!(VectorIndex::<serde_json::Value>::load(&path, &request), Err(OpenAiError::ModelMismatch{..})));
+    //saving over the file index is mapped from
+    loaded.save(&path)?;
+    let mut reopened: VectorIndex = VectorIndex::open(&path)?;
+    assert_eq!(reopened.get("a").unwrap().0, &[0.0, 0.0, 9.0]);
+    reopened.insert("c", vec![1.0, 1.0, 1.0], serde_json::json!({"page": 4}))?;
+    let unchanged: VectorIndex = VectorIndex::open(&path)?;
+    unchanged.save(&path)?;
+    assert_eq!(unchanged.get("b").unwrap().0, &[0.0, 1.0, 0.5]);
+    reopened.save(&path)?;
+    assert_eq!(VectorIndex::<serde_json::Value>::open(&path)?.len(), 3);
+    let leftovers = fs::read_dir(std::env::temp_dir())?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with("openai_req_index_test.idx.") && name.ends_with(".tmp"))
+        .count();
+    assert_eq!(leftovers, 0);
+    fs::write(&path, b"not an index")?;
+    assert!(matches!(VectorIndex::<serde_json::Value>::open(&path), Err(OpenAiError::Io(_))));
+    fs::remove_file(&path)?;
+    fs::remove_file(path.with_extension("idx.json"))?;
+    Ok(())
+}
+
+#[test]
+fn text_splitter() {
+    let sentence = "Tokio runs async tasks on a pool of worker threads. ";
+    let document = format!(
+        "Intro line.\n\n# Guide\n\n## Install\n\n{}\n\n```\n# not a heading\n```\n\n## Usage\n\n{}{}",
+        sentence.repeat(20), sentence.repeat(3), "x".repeat(2_000)
+    );
+    let chunks = TextSplitter::new("text-embedding-3-small").chunk_tokens(60).overlap(15).split(&document);
+    assert_eq!((chunks[0].text.as_str(), chunks[0].headings.len()), ("Intro line.", 0));
+    for chunk in &chunks {
+        assert_eq!(&document[chunk.range.clone()], chunk.text);
+        assert!(chunk.tokens <= 60, "{} tokens in {:?}", chunk.tokens, chunk.text);
+        assert!(!chunk.text.contains("## "));
+    }
+    let install = chunks.iter().filter(|chunk| chunk.headings == ["Guide", "Install"]).collect::<Vec<_>>();
+    assert!(install.len() > 2);
+    //consecutive chunks share whole sentences
+    assert!(install[1].range.start < install[0].range.end);
+    assert!(install[1].text.starts_with("Tokio"));
+    assert!(install.last().unwrap().text.ends_with("# not a heading\n```"));
+    let usage = chunks.iter().filter(|chunk| chunk.headings == ["Guide", "Usage"]).collect::<Vec<_>>();
+    assert!(usage.len() > 1);
+    assert!(usage[0].text.ends_with("threads."));
+    assert_eq!(usage.iter().map(|chunk| chunk.text.matches('x').count()).sum::<usize>(), 2_000);
+    //text without spaces, split at its own sentence ends
+    let document = "日本語の文章です。次の文。".repeat(3);
+    let chunks = TextSplitter::new("text-embedding-3-small").chunk_tokens(5).overlap(0).split(&document);
+    assert!(chunks.iter().all(|chunk| chunk.tokens <= 5 && document[chunk.range.clone()] == chunk.text));
+    assert_eq!(chunks.iter().map(|chunk| chunk.text.as_str()).collect::<String>(), document);
+    let chunks = TextSplitter::new("text-embedding-3-small").chunk_tokens(8).overlap(0).split(&document);
+    assert!(chunks.iter().all(|chunk| chunk.tokens <= 8 && chunk.text.ends_with('。')));
+}
+
+#[test]
+fn chat_params_validation() -> Result<(),anyhow::Error> {
+    let messages = vec![Message::new(Role::User, "hello!")];
+    let request = ChatRequest::with_model_and_messages("o3-mini", messages.clone())
+        .max_completion_tokens(500)
+        .reasoning_effort(ReasoningEffort::High)
+        .seed(42)
+        .service_tier(ServiceTier::Flex)
+        .store(true)
+        .metadata([("team".to_string(), "search".to_string())].into())
+        .include_usage(true);
+    assert!(StreamingRequest::validate(&request).is_ok());
+    //stream options are only accepted in streaming mode
+    assert!(matches!(JsonRequest::validate(&request), Err(OpenAiError::InvalidParameter{ param, .. }) if param == "stream_options"));
+    let json = serde_json::to_value(&request)?;
+    assert_eq!(json["reasoning_effort"], "high");
+    assert_eq!(json["service_tier"], "flex");
+    assert_eq!(json["stream_options"]["include_usage"], true);
+    let invalid = |request: ChatRequest| match request.check_model_params() {
+        Err(OpenAiError::InvalidParameter{ param, .. }) => param,
+        _ => String::new()
+    };
+    assert_eq!(invalid(ChatRequest::with_model_and_messages("o1", messages.clone()).max_tokens(10)), "max_tokens");
+    assert_eq!(invalid(ChatRequest::with_model_and_messages("gpt-5", messages.clone()).temperature(0.5)), "temperature");
+    assert_eq!(invalid(ChatRequest::with_model_and_messages("gpt-4o", messages.clone()).reasoning_effort(ReasoningEffort::Low)), "reasoning_effort");
+    assert_eq!(invalid(ChatRequest::with_model_and_messages("o3", messages.clone()).reasoning_effort(ReasoningEffort::Minimal)), "reasoning_effort");
+    assert_eq!(invalid(ChatRequest::new(messages.clone()).parallel_tool_calls(false)), "parallel_tool_calls");
+    assert!(ChatRequest::new(messages).max_tokens(10).temperature(0.5).check_model_params().is_ok());
+    let response: ChatSuccess = serde_json::from_str(r#"{"id":"1","object":"chat.completion","created":1,
+        "system_fingerprint":"fp_44709d6fcb","service_tier":"default",
+        "choices":[{"index":0,"finish_reason":"stop","message":{"role":"assistant","content":"Hi"}}],
+        "usage":{"prompt_tokens":2006,"completion_tokens":300,"total_tokens":2306,
+            "prompt_tokens_details":{"cached_tokens":1920,"audio_tokens":0},
+            "completion_tokens_details":{"reasoning_tokens":256,"audio_tokens":0,"accepted_prediction_tokens":0,"rejected_prediction_tokens":0}}}"#)?;
+    assert_eq!(response.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+    assert_eq!(response.usage.cached_tokens(), 1920);
+    assert_eq!(response.usage.reasoning_tokens(), 256);
+    Ok(())
+}
+
+#[test]
+fn logprobs_parsing() -> Result<(),anyhow::Error> {
+    let json = serde_json::to_value(ChatRequest::new(vec![]).top_logprobs(40))?;
+    assert_eq!(json["logprobs"], true);
+    assert_eq!(json["top_logprobs"], 20);
+    let response: ChatSuccess = serde_json::from_str(r#"{"id":"1","object":"chat.completion","created":1,
+        "choices":[{"index":0,"finish_reason":"stop","message":{"role":"assistant","content":"Yes!"},
+        "logprobs":{"content":[
+            {"token":"Yes","logprob":-0.1,"bytes":[89,101,115],"top_logprobs":[{"token":"Yes","logprob":-0.1,"bytes":[89,101,115]},{"token":"No","logprob":-2.4,"bytes":[78,111]}]},
+            {"token":"!","logprob":-0.7,"bytes":[33],"top_logprobs":[]}],"refusal":null}}],
+        "usage":{"prompt_tokens":1,"completion_tokens":2,"total_tokens":3}}"#)?;
+    let logprobs = response.choices[0].logprobs.as_ref().unwrap();
+    assert_eq!(logprobs.content.as_ref().unwrap()[0].top_logprobs[1].token, "No");
+    assert!((logprobs.sequence_logprob() + 0.8).abs() < 1e-9);
+    assert!((logprobs.sequence_probability() - (-0.8f64).exp()).abs() < 1e-9);
+    assert!((logprobs.min_confidence().unwrap() - (-0.7f64).exp()).abs() < 1e-9);
+    assert_eq!(logprobs.token_confidences()[0].0, "Yes");
+    let response: CompletionSuccess = serde_json::from_str(r#"{"id":"1","object":"text_completion","created":1,"model":"davinci-002",
+        "choices":[{"index":0,"finish_reason":"length","text":"Hi there","logprobs":{
+            "tokens":["Hi"," there"],"token_logprobs":[null,-0.5],
+            "top_logprobs":[null,{" there":-0.5," you":-1.5}],"text_offset":[0,2]}}],
+        "usage":{"prompt_tokens":1,"completion_tokens":2,"total_tokens":3}}"#)?;
+    let logprobs = response.choices[0].logprobs.as_ref().unwrap();
+    assert_eq!(logprobs.text_offset, vec![0, 2]);
+    //echoed first token has no logprob and is skipped
+    assert_eq!(logprobs.token_logprobs(), vec![(" there", -0.5)]);
+    assert_eq!(logprobs.mean_confidence(), Some((-0.5f64).exp()));
+    Ok(())
+}
+
+#[test]
+fn error_classification() {
+    let details = ApiErrorDetails{
+        message: "Rate limit reached".to_string(),
+        kind: "requests".to_string(),
+        param: None,
+        code: Some("rate_limit_exceeded".to_string())
+    };
+    let err = OpenAiError::from_status(reqwest::StatusCode::TOO_MANY_REQUESTS, details.clone());
+    assert!(matches!(err, OpenAiError::RateLimit{..}));
+    assert_eq!(err.status(), Some(reqwest::StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(err.details().unwrap().code.as_deref(), Some("rate_limit_exceeded"));
+    let err = OpenAiError::from_status(reqwest::StatusCode::UNAUTHORIZED, details.clone());
+    assert!(matches!(err, OpenAiError::Auth{..}));
+    let err = OpenAiError::from_status(reqwest::StatusCode::NOT_FOUND, details.clone());
+    assert!(matches!(err, OpenAiError::InvalidRequest{..}));
+    let err = OpenAiError::from_status(reqwest::StatusCode::BAD_GATEWAY, details);
+    assert!(matches!(err, OpenAiError::Server{..}));
+}
+
+#[test]
+fn retry_policy_delays() {
+    use std::time::Duration;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+    let policy = RetryPolicy::default()
+        .base_delay(Duration::from_millis(100))
+        .max_delay(Duration::from_millis(300))
+        .jitter(false);
+    let delays = (0..4).map(|attempt| policy.backoff(attempt).as_millis()).collect::<Vec<_>>();
+    assert_eq!(delays, vec![100, 200, 300, 300]);
+    let jittered = policy.clone().jitter(true).backoff(1);
+    assert!(jittered >= Duration::from_millis(100) && jittered <= Duration::from_millis(200));
+    let headers = |pairs: &[(&'static str, &'static str)]| pairs.iter()
+        .map(|(name, value)| (reqwest::header::HeaderName::from_static(name), HeaderValue::from_static(value)))
+        .collect::<HeaderMap>();
+    assert_eq!(policy.delay_for(1, &HeaderMap::new()), Duration::from_millis(200));
+    //header delays win over backoff and are not capped by max_delay
+    assert_eq!(policy.delay_for(0, &headers(&[("retry-after", "2")])), Duration::from_secs(2));
+    assert_eq!(policy.delay_for(0, &headers(&[("retry-after", "2"), ("retry-after-ms", "150")])), Duration::from_millis(150));
+    assert_eq!(policy.clone().respect_headers(false).delay_for(0, &headers(&[("retry-after", "2")])), Duration::from_millis(100));
+    //values that are not valid durations do not panic
+    assert_eq!(policy.delay_for(0, &headers(&[("retry-after", "inf")])), Duration::from_millis(300));
+    assert_eq!(policy.delay_for(0, &headers(&[("retry-after-ms", "1e400")])), Duration::from_millis(300));
+    assert_eq!(policy.delay_for(0, &headers(&[("retry-after", "NaN")])), Duration::from_millis(100));
+    let huge = headers(&[("x-ratelimit-remaining-requests", "0"), ("x-ratelimit-reset-requests", "1e300")]);
+    assert_eq!(policy.delay_for(0, &huge), Duration::from_millis(100));
+    //reset headers only count for exhausted limits
+    let limits = headers(&[
+        ("x-ratelimit-remaining-requests", "0"), ("x-ratelimit-reset-requests", "1s"),
+        ("x-ratelimit-remaining-tokens", "10"), ("x-ratelimit-reset-tokens", "6m0s")
+    ]);
+    assert_eq!(policy.delay_for(0, &limits), Duration::from_secs(1));
+    let limits = headers(&[("x-ratelimit-remaining-tokens", "5"), ("x-ratelimit-reset-tokens", "6m0s")]);
+    assert_eq!(policy.delay_for(0, &limits), Duration::from_millis(100));
+    assert!(policy.retries(StatusCode::TOO_MANY_REQUESTS));
+    assert!(!policy.retries(StatusCode::BAD_REQUEST));
+    let policy = policy.retry_on(vec![StatusCode::CONFLICT]);
+    assert!(policy.retries(StatusCode::CONFLICT));
+    assert!(!policy.retries(StatusCode::TOO_MANY_REQUESTS));
+}
+
+///local server, that answers `count` requests with empty json object, and returns them
+async fn record_requests(count: usize) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    mock_server(vec!["{}".to_string(); count]).await
+}
+
+///local server, that answers requests with json `responses` in order,
+///or sends them as is, if they are full http responses,
+///and returns requests it got, with lowercased head and raw body
+async fn mock_server(responses: Vec<String>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = vec![0u8; 65536];
+            let (head, body_start, length) = loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                    let length = head.lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .and_then(|length| length.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    break (head, end + 4, length)
+                }
+            };
+            while request.len() < body_start + length {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            requests.push(format!("{}\r\n\r\n{}", head, String::from_utf8_lossy(&request[body_start..])));
+            let reply = if response.starts_with("HTTP/") {
+                response
+            } else {
+                format!("HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}", response.len(), response)
+            };
+            socket.write_all(reply.as_bytes()).await.unwrap();
+        }
+        requests
+    });
+    (url, server)
+}
+
+///chat completion response with single assistant message
+fn chat_response(message: serde_json::Value, finish_reason: &str) -> String {
+    serde_json::json!({
+        "id": "1", "object": "chat.completion", "created": 1,
+        "choices": [{"index": 0, "finish_reason": finish_reason, "message": message}],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+    }).to_string()
+}
+
+#[tokio::test]
+async fn batcher_does_not_repeat_client_retries() -> Result<(),anyhow::Error> {
+    let error = "HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\ncontent-length: 0\r\n\r\n".to_string();
+    let (url, server) = mock_server(vec![error; 2]).await;
+    let policy = RetryPolicy::default().max_attempts(2).base_delay(std::time::Duration::from_millis(1));
+    let client = OpenAiClient::with_url("", &url).retry_policy(policy.clone());
+    let batcher = EmbeddingBatcher::new("text-embedding-3-small").retry_policy(policy);
+    let result = batcher.embed_all(&client, vec!["text".to_string()]).await;
+    assert_eq!(result.unwrap_err().status(), Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR));
+    assert_eq!(server.await?.len(), 2);
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn request_span_fields() -> Result<(),anyhow::Error> {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    ///fields of `openai_request` spans, recorded when span is created or later
+    #[derive(Clone, Default)]
+    struct Fields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.lock().unwrap().push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.lock().unwrap().push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Fields {
+        fn on_new_span(&self, attributes: &Attributes<'_>, _id: &Id, _context: Context<'_, S>) {
+            if attributes.metadata().name() == "openai_request" {
+                self.0.lock().unwrap().push(("span".to_string(), "openai_request".to_string()));
+                attributes.record(&mut self.clone());
+            }
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, context: Context<'_, S>) {
+            if context.span(id).is_some_and(|span| span.name() == "openai_request") {
+                values.record(&mut self.clone());
+            }
+        }
+    }
+
+    let fields = Fields::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(fields.clone()));
+    let answer = chat_response(serde_json::json!({"role": "assistant", "content": "hi"}), "stop");
+    let (url, server) = mock_server(vec![answer]).await;
+    let client = OpenAiClient::with_url("secret", &url);
+    ChatRequest::with_model_and_messages("gpt-4o-mini", vec![Message::new(Role::User, "hello!")]).run(&client).await?;
+    server.await?;
+    let recorded = fields.0.lock().unwrap().clone();
+    let field = |name: &str| recorded.iter().rev().find(|(field, _)| field == name).map(|(_, value)| value.as_str());
+    assert_eq!(recorded.iter().filter(|(field, _)| field == "span").count(), 1);
+    assert_eq!(field("method"), Some("POST"));
+    assert_eq!(field("endpoint"), Some("/chat/completions"));
+    assert_eq!(field("model"), Some("gpt-4o-mini"));
+    assert_eq!((field("status"), field("attempts")), (Some("200"), Some("1")));
+    assert!(field("latency_ms").is_some());
+    assert_eq!((field("prompt_tokens"), field("completion_tokens"), field("total_tokens")), (Some("1"), Some("1"), Some("2")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn azure_urls() -> Result<(),anyhow::Error> {
+    let (url, server) = record_requests(5).await;
+    let azure = AzureConfig::new("2024-02-01")
+        .deployment("gpt-35-turbo", "chat-prod")
+        .endpoint_deployment("/images/generations", "dalle");
+    let client = OpenAiClient::azure("secret", &(url + "/"), azure);
+    let messages = vec![Message::new(Role::User, "hello!")];
+    //responses are not valid, only requests are checked
+    let _ = ChatRequest::with_model_and_messages("gpt-35-turbo", messages).run(&client).await;
+    let _ = EmbeddingRequest::with_model("text-embedding-3-small".to_string(), "hello".into()).run(&client).await;
+    let _ = ImageRequest::new("cat".to_string()).run(&client).await;
+    let _ = ModelListResponse::get(&client).await;
+    let _ = FileInfoRequest::new("file-1".to_string()).run(&client).await;
+    let requests = server.await?;
+    let lines = requests.iter().map(|request| request.lines().next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(lines, vec![
+        "post /openai/deployments/chat-prod/chat/completions?api-version=2024-02-01 http/1.1",
+        "post /openai/deployments/text-embedding-3-small/embeddings?api-version=2024-02-01 http/1.1",
+        "post /openai/deployments/dalle/images/generations?api-version=2024-02-01 http/1.1",
+        "get /openai/models?api-version=2024-02-01 http/1.1",
+        "get /openai/files/file-1?api-version=2024-02-01 http/1.1"
+    ]);
+    for request in &requests {
+        assert!(request.contains("\r\napi-key: secret"));
+        assert!(!request.contains("authorization"));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn builder_headers() -> Result<(),anyhow::Error> {
+    let (url, server) = record_requests(2).await;
+    let client = OpenAiClient::builder("secret")
+        .url(&url)
+        .organization("org-123")
+        .project("proj_123")
+        .header("X-Team", "search")
+        .build()?;
+    //responses are not valid, only requests are checked
+    let _ = ChatRequest::new(vec![Message::new(Role::User, "hello!")]).run(&client).await;
+    let _ = ModelListResponse::get(&client).await;
+    for request in server.await? {
+        assert!(request.contains("\r\nopenai-organization: org-123\r\n"));
+        assert!(request.contains("\r\nopenai-project: proj_123\r\n"));
+        assert!(request.contains("\r\nx-team: search\r\n"));
+        assert!(request.contains("\r\nauthorization: bearer secret\r\n"));
+    }
+    Ok(())
+}
+
+#[test]
+fn response_meta_headers() {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-request-id", "req_123".parse().unwrap());
+    headers.insert("openai-processing-ms", "250".parse().unwrap());
+    headers.insert("x-ratelimit-remaining-requests", "59".parse().unwrap());
+    headers.insert("x-ratelimit-remaining-tokens", "149000".parse().unwrap());
+    headers.insert("x-ratelimit-reset-requests", "1s".parse().unwrap());
+    headers.insert("x-ratelimit-reset-tokens", "6m0.5s".parse().unwrap());
+    let meta = ResponseMeta::from_headers(reqwest::StatusCode::OK, &headers);
+    assert_eq!(meta.request_id.as_deref(), Some("req_123"));
+    assert_eq!(meta.processing_ms, Some(250));
+    assert_eq!(meta.rate_limit.remaining_requests, Some(59));
+    assert_eq!(meta.rate_limit.remaining_tokens, Some(149000));
+    assert_eq!(meta.rate_limit.reset_requests, Some(std::time::Duration::from_secs(1)));
+    assert_eq!(meta.rate_limit.reset_tokens, Some(std::time::Duration::from_millis(360500)));
+    assert_eq!(meta.rate_limit.limit_tokens, None);
+}
+
+#[tokio::test]
+async fn limiter_queues_and_settles() {
+    let limiter = RateLimiter::new().model("gpt-3.5-turbo", ModelLimits::tokens(6000));
+    let start = std::time::Instant::now();
+    limiter.acquire("gpt-3.5-turbo", 6000).await;
+    limiter.acquire("gpt-3.5-turbo", 50).await;
+    assert!(start.elapsed() >= std::time::Duration::from_millis(400));
+    //unknown models are not limited
+    limiter.acquire("gpt-4", 1_000_000).await;
+    //actual usage lower than estimate returns tokens to budget
+    limiter.settle("gpt-3.5-turbo", 6000, 0);
+    let start = std::time::Instant::now();
+    limiter.acquire("gpt-3.5-turbo", 3000).await;
+    assert!(start.elapsed() < std::time::Duration::from_millis(100));
+}
+
+#[test]
+fn token_estimate() {
+    let request = ChatRequest::new(vec![Message::new(Role::User, "hello there!")])
+        .max_tokens(100);
+    let estimate = estimate_tokens(&serde_json::to_value(request).unwrap());
+    assert!(estimate > 100 && estimate < 120);
+}
+
+///file key is re-read only after file was modified
+#[tokio::test]
+async fn file_key_rereads_on_change() -> Result<(),anyhow::Error> {
+    let path = std::env::temp_dir().join("openai_req_file_key_test");
+    fs::write(&path, "first-key\n")?;
+    let provider = FileKey::new(path.clone());
+    assert!(matches!(provider.credential().await?, Credential::Key(key) if key == "first-key"));
+    fs::write(&path, "second-key")?;
+    fs::File::options().write(true).open(&path)?
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5))?;
+    assert!(matches!(provider.credential().await?, Credential::Key(key) if key == "second-key"));
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn embeddings_with_meta()-> Result<(),anyhow::Error> {
+    let client = get_client();
+    let embedding_request
+        = EmbeddingRequest::new("The food was delicious and the waiter...".into());
+    let response = embedding_request.run_with_meta(&client).await?;
+    dbg!(&response.meta);
+    assert!(response.meta.request_id.is_some());
+    Ok(())
+}
 
 #[tokio::test]
 async fn edit()-> Result<(),anyhow::Error> {
@@ -77,6 +1007,18 @@ async fn completion()-> Result<(),anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn completion_stream()-> Result<(),anyhow::Error> {
+    let client = get_client();
+    let mut completion_request = CompletionRequest::new("long long time ago".into());
+    completion_request.set_n(2);
+    let stream = completion_request.run_stream(&client).await?;
+    let response = CompletionSuccess::collect_stream(stream).await?;
+    dbg!(&response);
+    assert_eq!(response.choices.len(), 2);
+    Ok(())
+}
+
 #[tokio::test]
 async fn models()-> Result<(),anyhow::Error> {
     let client = get_client();